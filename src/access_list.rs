@@ -0,0 +1,162 @@
+/// EIP-2930 access lists, as given in requests and as created from an execution.
+use std::collections::{BTreeMap, BTreeSet};
+
+use evm::executor::stack::{Accessed, StackSubstateMetadata};
+use primitive_types::{H160, H256};
+
+/// An entry of an access list, in the format of Ethereum JSON-RPC.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AccessListItem {
+    pub address: H160,
+    pub storage_keys: Vec<H256>,
+}
+
+/// Marks `addresses` and the entries of `access_list` as accessed, so that the execution
+/// is charged for warm accesses to them. A no-op before Berlin.
+pub fn warm_up(
+    metadata: &mut StackSubstateMetadata,
+    addresses: &BTreeSet<H160>,
+    access_list: &[AccessListItem],
+) {
+    metadata.access_addresses(
+        addresses
+            .iter()
+            .copied()
+            .chain(access_list.iter().map(|item| item.address)),
+    );
+    metadata.access_storages(access_list.iter().flat_map(|item| {
+        item.storage_keys
+            .iter()
+            .map(move |key| (item.address, *key))
+    }));
+}
+
+/// The pairs taken by `StackExecutor::transact_call`.
+pub fn to_pairs(access_list: &[AccessListItem]) -> Vec<(H160, Vec<H256>)> {
+    access_list
+        .iter()
+        .map(|item| (item.address, item.storage_keys.clone()))
+        .collect()
+}
+
+/// The access list of all addresses and slots in `accessed`, like geth's
+/// `eth_createAccessList`. The addresses in `warm` are warm anyway, so they are left out,
+/// unless some of their slots were accessed: those are listed, as they are not warm.
+pub fn from_accessed(accessed: &Accessed, warm: &BTreeSet<H160>) -> Vec<AccessListItem> {
+    let mut entries: BTreeMap<H160, Vec<H256>> = accessed
+        .accessed_addresses
+        .iter()
+        .filter(|address| !warm.contains(address))
+        .map(|address| (*address, Vec::new()))
+        .collect();
+    for (address, key) in &accessed.accessed_storage {
+        entries.entry(*address).or_default().push(*key);
+    }
+    entries
+        .into_iter()
+        .map(|(address, storage_keys)| AccessListItem {
+            address,
+            storage_keys,
+        })
+        .collect()
+}
+
+/// EVM gas saved by executing with `access_list` rather than without, assuming every entry
+/// is accessed. The addresses in `warm` neither save nor cost anything, only their slots
+/// do. If `intrinsic_cost` is set, as in a transaction, it is net of the intrinsic cost of
+/// the list. Negative if it costs more than it saves.
+pub fn gas_saving(
+    access_list: &[AccessListItem],
+    warm: &BTreeSet<H160>,
+    config: &evm::Config,
+    intrinsic_cost: bool,
+) -> i64 {
+    if !config.increase_state_access_gas {
+        return 0;
+    }
+    let (address_cost, key_cost) = if intrinsic_cost {
+        (
+            config.gas_access_list_address as i64,
+            config.gas_access_list_storage_key as i64,
+        )
+    } else {
+        (0, 0)
+    };
+    let address_saving =
+        config.gas_account_access_cold as i64 - config.gas_storage_read_warm as i64 - address_cost;
+    let key_saving = config.gas_sload_cold as i64 - config.gas_storage_read_warm as i64 - key_cost;
+    access_list
+        .iter()
+        .map(|item| {
+            let address_saving = if warm.contains(&item.address) {
+                0
+            } else {
+                address_saving
+            };
+            address_saving + key_saving * item.storage_keys.len() as i64
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_accessed_keeps_the_slots_of_warm_addresses() {
+        let (caller, callee, other) = (
+            H160::repeat_byte(1),
+            H160::repeat_byte(2),
+            H160::repeat_byte(3),
+        );
+        let key = H256::repeat_byte(9);
+        let accessed = Accessed {
+            accessed_addresses: [caller, callee, other].into_iter().collect(),
+            accessed_storage: [(callee, key), (other, key)].into_iter().collect(),
+        };
+        let warm = [caller, callee].into_iter().collect();
+        assert_eq!(
+            from_accessed(&accessed, &warm),
+            vec![
+                AccessListItem {
+                    address: callee,
+                    storage_keys: vec![key],
+                },
+                AccessListItem {
+                    address: other,
+                    storage_keys: vec![key],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn gas_saving_is_net_of_the_intrinsic_cost() {
+        let access_list = vec![AccessListItem {
+            address: H160::repeat_byte(3),
+            storage_keys: vec![H256::repeat_byte(1), H256::repeat_byte(2)],
+        }];
+        // (2600 - 100 - 2400) + 2 * (2100 - 100 - 1900)
+        let cold = BTreeSet::new();
+        assert_eq!(
+            gas_saving(&access_list, &cold, &evm::Config::berlin(), true),
+            300
+        );
+        // Without the intrinsic cost: (2600 - 100) + 2 * (2100 - 100)
+        assert_eq!(
+            gas_saving(&access_list, &cold, &evm::Config::berlin(), false),
+            6_500
+        );
+        // Only the slots of a warm address: 2 * (2100 - 100 - 1900)
+        let warm = [H160::repeat_byte(3)].into_iter().collect();
+        assert_eq!(
+            gas_saving(&access_list, &warm, &evm::Config::berlin(), true),
+            200
+        );
+        assert_eq!(
+            gas_saving(&access_list, &cold, &evm::Config::istanbul(), true),
+            0
+        );
+    }
+}
@@ -0,0 +1,80 @@
+/// Errors that prevent an execution from completing.
+use std::any::Any;
+use std::fmt;
+
+use jsonrpc_core::{serde_json, ErrorCode, Value};
+use log::error;
+
+/// Serialized as `{"code": "<snake_case variant>", "message": "..."}`, so that the node
+/// can decide whether to retry the execution or to fail the block.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "code", content = "message", rename_all = "snake_case")]
+pub enum EvmError {
+    /// Could not connect to the node socket. Worth retrying.
+    NodeUnreachable(String),
+    /// The node did not answer a query in time. Worth retrying.
+    Timeout(String),
+    /// The node answered with state that cannot be interpreted.
+    MalformedState(String),
+    /// The EVM itself failed: a fatal exit reason, or an unexpected panic.
+    EvmFatal(String),
+}
+
+impl EvmError {
+    /// Abort the execution in progress with this error. The `Backend` interface of the
+    /// EVM has no way to return errors, so we unwind up to the `catch_unwind` around the
+    /// execution, which recovers the error with `from_panic`.
+    pub fn abort(self) -> ! {
+        error!("Aborting execution: {}", self);
+        std::panic::resume_unwind(Box::new(self))
+    }
+
+    /// The error behind a caught panic.
+    pub fn from_panic(panic: Box<dyn Any + Send>) -> Self {
+        match panic.downcast::<EvmError>() {
+            Ok(error) => *error,
+            Err(panic) => {
+                let panic_message = match panic.downcast::<String>() {
+                    Ok(message) => *message,
+                    Err(panic) => panic.downcast_ref::<&str>().map_or_else(
+                        || "unknown panic".to_string(),
+                        |message| message.to_string(),
+                    ),
+                };
+                error!("EVM panicked: '{:?}'", panic_message);
+                EvmError::EvmFatal(panic_message)
+            }
+        }
+    }
+
+    /// The `code` of the serialized error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EvmError::NodeUnreachable(_) => "node_unreachable",
+            EvmError::Timeout(_) => "timeout",
+            EvmError::MalformedState(_) => "malformed_state",
+            EvmError::EvmFatal(_) => "evm_fatal",
+        }
+    }
+}
+
+impl fmt::Display for EvmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvmError::NodeUnreachable(message) => write!(f, "node unreachable: {}", message),
+            EvmError::Timeout(message) => write!(f, "node timeout: {}", message),
+            EvmError::MalformedState(message) => write!(f, "malformed state: {}", message),
+            EvmError::EvmFatal(message) => write!(f, "EVM execution failed: {}", message),
+        }
+    }
+}
+
+impl From<EvmError> for jsonrpc_core::Error {
+    fn from(error: EvmError) -> Self {
+        jsonrpc_core::Error {
+            code: ErrorCode::ServerError(-32001),
+            message: error.to_string(),
+            data: Some(serde_json::to_value(&error).unwrap_or(Value::Null)),
+        }
+    }
+}
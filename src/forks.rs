@@ -0,0 +1,174 @@
+/// Hard fork selection: which set of EVM rules applies at a given block.
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use evm::executor::stack::PrecompileFn;
+use primitive_types::{H160, U256};
+
+use crate::precompiles;
+
+/// Ethereum hard forks for which the EVM crate provides a configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Fork {
+    Frontier,
+    Istanbul,
+    Berlin,
+    London,
+}
+
+impl Fork {
+    /// EVM configuration (opcodes, gas costs, limits) of the fork.
+    pub fn config(self) -> evm::Config {
+        match self {
+            Fork::Frontier => evm::Config::frontier(),
+            Fork::Istanbul => evm::Config::istanbul(),
+            Fork::Berlin => evm::Config::berlin(),
+            Fork::London => evm::Config::london(),
+        }
+    }
+
+    /// Precompiled contracts that are active in the fork.
+    pub fn precompiles(self) -> BTreeMap<H160, PrecompileFn> {
+        precompiles::for_fork(self)
+    }
+}
+
+impl FromStr for Fork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "frontier" => Ok(Fork::Frontier),
+            "istanbul" => Ok(Fork::Istanbul),
+            "berlin" => Ok(Fork::Berlin),
+            "london" => Ok(Fork::London),
+            // Its PUSH0 opcode and warm coinbase are not in the EVM crate, not even as options
+            // of a custom configuration.
+            "shanghai" => Err(format!("fork '{}' is not supported", s)),
+            _ => Err(format!("unknown fork '{}'", s)),
+        }
+    }
+}
+
+/// Block number -> fork activations. Before the first activation, `default` applies.
+#[derive(Clone, Debug)]
+pub struct ForkSchedule {
+    default: Fork,
+    activations: BTreeMap<u64, Fork>,
+}
+
+impl ForkSchedule {
+    pub fn new(default: Fork) -> Self {
+        Self {
+            default,
+            activations: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_activations(mut self, activations: BTreeMap<u64, Fork>) -> Self {
+        self.activations = activations;
+        self
+    }
+
+    /// Whether the fork depends on the block number at all. If not, there is
+    /// no need to ask the node for the current block.
+    pub fn is_fixed(&self) -> bool {
+        self.activations.is_empty()
+    }
+
+    /// The fork active at `block_number`.
+    pub fn fork_at(&self, block_number: U256) -> Fork {
+        // Blocks beyond u64 are beyond any activation as well.
+        let block_number = if block_number > U256::from(u64::MAX) {
+            u64::MAX
+        } else {
+            block_number.as_u64()
+        };
+        self.activations
+            .range(..=block_number)
+            .next_back()
+            .map(|(_, fork)| *fork)
+            .unwrap_or(self.default)
+    }
+}
+
+/// Parse a list of activations of the form `<block>=<fork>,<block>=<fork>,...`,
+/// e.g. `0=istanbul,1500000=berlin,2000000=london`.
+pub fn parse_activations(s: &str) -> Result<BTreeMap<u64, Fork>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (block, fork) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("fork activation '{}' is not <block>=<fork>", entry))?;
+            let block = block
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| format!("fork activation block '{}': {}", block, e))?;
+            Ok((block, fork.trim().parse()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fork_at_activation_boundaries() {
+        let schedule = ForkSchedule::new(Fork::Frontier).with_activations(
+            [(10, Fork::Istanbul), (20, Fork::Berlin), (30, Fork::London)]
+                .into_iter()
+                .collect(),
+        );
+        assert!(!schedule.is_fixed());
+        let forks: Vec<Fork> = [0, 9, 10, 19, 20, 29, 30, 1_000]
+            .into_iter()
+            .map(|block| schedule.fork_at(U256::from(block)))
+            .collect();
+        assert_eq!(
+            forks,
+            [
+                Fork::Frontier,
+                Fork::Frontier,
+                Fork::Istanbul,
+                Fork::Istanbul,
+                Fork::Berlin,
+                Fork::Berlin,
+                Fork::London,
+                Fork::London,
+            ]
+        );
+        assert_eq!(schedule.fork_at(U256::MAX), Fork::London);
+
+        let fixed = ForkSchedule::new(Fork::Berlin);
+        assert!(fixed.is_fixed());
+        assert_eq!(fixed.fork_at(U256::MAX), Fork::Berlin);
+    }
+
+    #[test]
+    fn activations() {
+        assert_eq!(
+            parse_activations(" 0=istanbul, 1500000=Berlin,2000000=london,").unwrap(),
+            [
+                (0, Fork::Istanbul),
+                (1_500_000, Fork::Berlin),
+                (2_000_000, Fork::London)
+            ]
+            .into_iter()
+            .collect()
+        );
+        assert_eq!(parse_activations("").unwrap(), BTreeMap::new());
+        for (malformed, error) in [
+            ("0=istanbul,berlin", "is not <block>=<fork>"),
+            ("x=berlin", "fork activation block 'x'"),
+            ("-1=berlin", "fork activation block '-1'"),
+            ("0=shanghai", "fork 'shanghai' is not supported"),
+            ("0=cancun", "unknown fork 'cancun'"),
+        ] {
+            let e = parse_activations(malformed).unwrap_err();
+            assert!(e.contains(error), "{}: {}", malformed, e);
+        }
+    }
+}
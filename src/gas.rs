@@ -0,0 +1,119 @@
+/// Conversion between Scilla gas, which the node charges for, and EVM gas.
+use std::str::FromStr;
+
+/// How EVM gas used that is not a whole number of Scilla gas is charged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GasRounding {
+    /// Round down, in favour of the caller.
+    Floor,
+    /// Round up, so that all EVM gas used is paid for.
+    Ceil,
+}
+
+impl FromStr for GasRounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "floor" => Ok(GasRounding::Floor),
+            "ceil" => Ok(GasRounding::Ceil),
+            _ => Err(format!("unknown gas rounding '{}'", s)),
+        }
+    }
+}
+
+/// Gas limits are given in Scilla gas, and scaled to EVM gas exactly. Only the gas used
+/// is rounded back, and the remaining gas is what is left of the limit, so that the gas
+/// used and the remaining gas always add up to the limit, in both units.
+#[derive(Clone, Copy, Debug)]
+pub struct GasScaling {
+    // How much EVM gas is one Scilla gas worth.
+    factor: u64,
+    rounding: GasRounding,
+}
+
+/// Gas used by an execution, and what is left of its limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GasUsage {
+    /// In Scilla gas units.
+    pub used: u64,
+    /// In Scilla gas units.
+    pub remaining: u64,
+    pub evm_used: u64,
+}
+
+impl GasScaling {
+    pub fn new(factor: u64, rounding: GasRounding) -> Self {
+        assert!(factor > 0, "gas scaling factor must be positive");
+        Self { factor, rounding }
+    }
+
+    /// The EVM gas limit for `scilla_gas`, or `None` if it does not fit in 64 bits.
+    pub fn to_evm(&self, scilla_gas: u64) -> Option<u64> {
+        scilla_gas.checked_mul(self.factor)
+    }
+
+    /// The Scilla gas charged for `evm_gas` used.
+    pub fn to_scilla(&self, evm_gas: u64) -> u64 {
+        match self.rounding {
+            GasRounding::Floor => evm_gas / self.factor,
+            GasRounding::Ceil => evm_gas / self.factor + u64::from(evm_gas % self.factor != 0),
+        }
+    }
+
+    /// The largest Scilla gas limit that fits in `evm_gas_limit`.
+    pub fn limit_to_scilla(&self, evm_gas_limit: u64) -> u64 {
+        evm_gas_limit / self.factor
+    }
+
+    /// The usage of an execution with `evm_gas_limit`, given as returned by `to_evm`,
+    /// of which `evm_remaining` is left.
+    pub fn usage(&self, evm_gas_limit: u64, evm_remaining: u64) -> GasUsage {
+        let evm_used = evm_gas_limit.saturating_sub(evm_remaining);
+        let used = self.to_scilla(evm_used);
+        GasUsage {
+            used,
+            remaining: self.limit_to_scilla(evm_gas_limit).saturating_sub(used),
+            evm_used,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounding() {
+        let floor = GasScaling::new(100, GasRounding::Floor);
+        let ceil = GasScaling::new(100, GasRounding::Ceil);
+        assert_eq!((floor.to_scilla(199), ceil.to_scilla(199)), (1, 2));
+        assert_eq!((floor.to_scilla(200), ceil.to_scilla(200)), (2, 2));
+        assert_eq!((floor.to_scilla(0), ceil.to_scilla(0)), (0, 0));
+        assert_eq!(ceil.to_scilla(u64::MAX), u64::MAX / 100 + 1);
+    }
+
+    #[test]
+    fn overflow() {
+        let scaling = GasScaling::new(100, GasRounding::Ceil);
+        assert_eq!(scaling.to_evm(21_000), Some(2_100_000));
+        assert_eq!(scaling.to_evm(u64::MAX / 100 + 1), None);
+    }
+
+    #[test]
+    fn usage_adds_up_to_the_limit() {
+        for rounding in [GasRounding::Floor, GasRounding::Ceil] {
+            let scaling = GasScaling::new(100, rounding);
+            let limit = scaling.to_evm(1_000).unwrap();
+            for evm_remaining in [0, 1, 99, 100, 12_345, limit] {
+                let usage = scaling.usage(limit, evm_remaining);
+                assert_eq!(usage.used + usage.remaining, 1_000);
+                assert_eq!(usage.evm_used + evm_remaining, limit);
+            }
+        }
+        let usage = GasScaling::new(100, GasRounding::Ceil).usage(100_000, 12_345);
+        assert_eq!((usage.used, usage.remaining), (877, 123));
+        let usage = GasScaling::new(100, GasRounding::Floor).usage(100_000, 12_345);
+        assert_eq!((usage.used, usage.remaining), (876, 124));
+    }
+}
@@ -3,35 +3,65 @@
 // #![deny(warnings)]
 #![forbid(unsafe_code)]
 
+mod access_list;
+mod error;
+mod forks;
+mod gas;
 mod ipc_connect;
+mod memorybackend;
+mod metrics;
+#[cfg(test)]
+mod mocknode;
+mod overrides;
 mod precompiles;
 mod protos;
+mod recording;
+mod revert;
 mod scillabackend;
+#[cfg(test)]
+mod statetests;
+mod tracers;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use clap::Parser;
 use evm::{
-    backend::{Apply, Basic},
-    executor::stack::{MemoryStackState, PrecompileFn, StackSubstateMetadata},
+    backend::{Apply, Backend, Basic},
+    executor::stack::{
+        MemoryStackState, PrecompileFn, StackExecutor, StackState, StackSubstateMetadata,
+    },
     tracing,
 };
 
 use serde::ser::{Serialize, SerializeStructVariant, Serializer};
+use sha3::Digest;
 
 use core::str::FromStr;
-use log::{debug, error, info};
+use log::{debug, info};
 
-use jsonrpc_core::{BoxFuture, Error, IoHandler, Result};
+use access_list::AccessListItem;
+use error::EvmError;
+use forks::{Fork, ForkSchedule};
+use gas::{GasRounding, GasScaling};
+use jsonrpc_core::{serde_json, BoxFuture, Error, ErrorCode, IoHandler, Params, Result, Value};
 use jsonrpc_derive::rpc;
 use jsonrpc_server_utils::codecs;
+use memorybackend::{InMemoryBackend, MemoryState};
+use overrides::{BlockOverrides, EnvOverrides, OverrideBackend, StateOverrides};
 use primitive_types::*;
-use scillabackend::{ScillaBackend, ScillaBackendConfig};
+use recording::Recorder;
+use revert::decode_revert_reason;
+use scillabackend::{
+    NodePool, NodeQuery, PrecisionPolicy, ScillaBackend, ScillaBackendConfig, ZilliqaBackend,
+};
+use tracers::{SharedTracer, StructLoggerConfig, TraceOutcome, TracerConfig, TracingBackend};
 
 /// EVM JSON-RPC server
 #[derive(Parser, Debug)]
@@ -61,12 +91,97 @@ struct Args {
     #[clap(long, default_value = "100")]
     gas_scaling_factor: u64,
 
+    /// Whether EVM gas used is rounded "floor" or "ceil" to Scilla gas.
+    #[clap(long, default_value = "ceil")]
+    gas_rounding: GasRounding,
+
     /// Zil scaling factor.  How many Zils in one EVM visible Eth.
     #[clap(long, default_value = "1")]
     zil_scaling_factor: u64,
+
+    /// What to do with balances that are not a whole number of Zils after an execution:
+    /// "truncate" them, "reject" the execution, or report the remainder as "dust".
+    #[clap(long, default_value = "dust")]
+    precision_policy: PrecisionPolicy,
+
+    /// File to append the requests of executions to, with the queries they make to the
+    /// node and their results, to replay them with the `replay` command.
+    #[clap(long)]
+    record: Option<PathBuf>,
+
+    /// Hard fork rules to apply before the first activation in `fork_schedule`: "frontier",
+    /// "istanbul", "berlin" or "london". Shanghai and later forks are not supported.
+    #[clap(long, default_value = "london")]
+    fork: Fork,
+
+    /// Fork activations by block number, e.g. "0=istanbul,1500000=berlin,2000000=london".
+    #[clap(long, parse(try_from_str = forks::parse_activations))]
+    fork_schedule: Option<BTreeMap<u64, Fork>>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Execute code against the state of a JSON file, without a node, and print the result.
+    Exec(ExecArgs),
+    /// Execute again the executions of a recording, without a node, and print how their
+    /// results differ from the recorded ones.
+    Replay(ReplayArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ExecArgs {
+    /// EVM code to execute, in hex. Only for the raw mode.
+    #[clap(long, default_value = "")]
+    code: String,
+
+    /// Call data, in hex.
+    #[clap(long, default_value = "")]
+    data: String,
+
+    /// JSON file with the accounts and the environment of the execution.
+    #[clap(long)]
+    state: Option<PathBuf>,
+
+    /// Address of the executed contract.
+    #[clap(long, default_value = "0x0000000000000000000000007265636569766572")]
+    address: String,
+
+    #[clap(long, default_value = "0x000000000000000000000000000073656e646572")]
+    caller: String,
+
+    /// Message funds, in Zil units.
+    #[clap(long, default_value = "0")]
+    value: String,
+
+    /// In Scilla gas units.
+    #[clap(long, default_value = "1000000")]
+    gas_limit: u64,
+
+    /// "raw" or "transaction", as in `run`.
+    #[clap(long)]
+    mode: Option<String>,
+
+    /// "structLogger", "callTracer" or "prestateTracer".
+    #[clap(long)]
+    tracer: Option<TracerConfig>,
+
+    /// JSON file to write the state to after the execution, with its changes if it succeeded.
+    #[clap(long)]
+    dump: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReplayArgs {
+    /// The file written with `--record`.
+    recording: PathBuf,
 }
 
-struct DirtyState(Apply<Vec<(String, String)>>);
+/// A state change, and the remainder of its balance in Eth units that does not fit in
+/// Zil units, if the precision policy is to report it.
+struct DirtyState(Apply<Vec<(String, String)>>, Option<U256>);
 
 impl Serialize for DirtyState {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
@@ -88,6 +203,9 @@ impl Serialize for DirtyState {
                 state.serialize_field("code", &code.as_ref().map(hex::encode))?;
                 state.serialize_field("storage", storage)?;
                 state.serialize_field("reset_storage", &reset_storage)?;
+                if let Some(dust) = &self.1 {
+                    state.serialize_field("dust", dust)?;
+                }
                 Ok(state.end()?)
             }
             Apply::Delete { address } => {
@@ -103,12 +221,142 @@ impl Serialize for DirtyState {
 pub struct EvmResult {
     exit_reason: evm::ExitReason,
     return_value: String,
+    /// Decoded `return_value` of a revert, if it has any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revert_reason: Option<String>,
     apply: Vec<DirtyState>,
     logs: Vec<ethereum::Log>,
+    /// In Scilla gas units. Adds up to the gas limit with `gas_used`.
     remaining_gas: u64,
+    /// In Scilla gas units, rounded according to `--gas-rounding`.
+    gas_used: u64,
+    evm_gas_used: u64,
+    /// Address of the created contract, only for successful `create` and `create2`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contract_address: Option<H160>,
+    /// Why the execution could not complete, if it did not.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<EvmError>,
+    /// Output of the tracer, only for traced executions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<Value>,
+    /// What happened before the execution failed with a panic.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partial: Option<PartialResult>,
+}
+
+/// The state changes and logs of an execution that failed with a panic, and the gas it
+/// used. The state changes must not be applied, they are only there for diagnosis.
+#[derive(serde::Serialize)]
+pub struct PartialResult {
+    apply: Vec<DirtyState>,
+    logs: Vec<ethereum::Log>,
+    /// In Scilla gas units.
+    gas_used: u64,
+}
+
+/// Result of the read-only `call`.
+#[derive(serde::Serialize)]
+pub struct CallResult {
+    exit_reason: evm::ExitReason,
+    return_value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revert_reason: Option<String>,
+    /// In Scilla gas units.
+    gas_used: u64,
+    evm_gas_used: u64,
+}
+
+/// Parameters of an execution of existing code, by name in `run_v2`. The positional
+/// methods take the fields up to `overrides` in order.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunParams {
+    address: String,
+    caller: String,
+    code: String,
+    data: String,
+    /// In Zil units.
+    apparent_value: String,
+    /// In Scilla gas units.
+    gas_limit: u64,
+    fork: Option<String>,
+    mode: Option<String>,
+    overrides: Option<StateOverrides>,
+    /// `ORIGIN` of the execution, instead of the one of the node.
+    origin: Option<H160>,
+    /// `GASPRICE` of the execution, in Eth units.
+    gas_price: Option<U256>,
+    block_overrides: Option<BlockOverrides>,
+    /// EIP-2930 access list, warm from the start of the execution.
+    access_list: Option<Vec<AccessListItem>>,
+    #[serde(default)]
+    is_static: bool,
+    tracer: Option<TracerConfig>,
+}
+
+/// Result of `create_access_list`.
+#[derive(serde::Serialize)]
+pub struct AccessListResult {
+    exit_reason: evm::ExitReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revert_reason: Option<String>,
+    access_list: Vec<AccessListItem>,
+    /// In Scilla gas units.
+    gas_used: u64,
+    evm_gas_used: u64,
+    /// EVM gas saved by executing with `access_list`, net of its intrinsic cost.
+    evm_gas_saving: i64,
+}
+
+/// Parameters of a `create` or `create2`, as recorded with `--record`.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+struct CreateParams {
+    caller: String,
+    code: String,
+    /// Only for `create2`.
+    salt: Option<String>,
+    /// In Zil units.
+    apparent_value: String,
+    /// In Scilla gas units.
+    gas_limit: u64,
+    fork: Option<String>,
+}
+
+/// The request of a recorded execution, by kind.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RecordedRequest {
+    Run(RunParams),
+    Create(CreateParams),
+    /// The fields of `RunParams` taken by `estimate_gas`, the others are left out.
+    EstimateGas(RunParams),
+}
+
+/// An execution, as recorded with `--record`.
+#[derive(serde::Deserialize, serde::Serialize)]
+struct RecordedRun {
+    request: RecordedRequest,
+    /// The queries to the node during the execution, in order.
+    queries: Vec<NodeQuery>,
+    /// The serialized `EvmResult`, or the gas of `estimate_gas`, or the error.
+    result: std::result::Result<Value, Error>,
+}
+
+/// What an execution yields besides its `EvmResult`.
+struct ExecutionDetails {
+    /// The state changes of `EvmResult::apply`, in EVM units.
+    state_apply: Vec<Apply<BTreeMap<H256, H256>>>,
+    /// Addresses and slots accessed. Addresses warm from the start are only listed with
+    /// their slots.
+    access_list: Vec<AccessListItem>,
+    /// In EVM gas units.
+    access_list_gas_saving: i64,
 }
 
+// The positional params are the interface of the node, new ones go in `RunParams`.
 #[rpc(server)]
+#[allow(clippy::too_many_arguments)]
 pub trait Rpc: Send + 'static {
     #[rpc(name = "run")]
     fn run(
@@ -119,13 +367,128 @@ pub trait Rpc: Send + 'static {
         data: String,
         apparent_value: String,
         gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+        overrides: Option<StateOverrides>,
+    ) -> BoxFuture<Result<EvmResult>>;
+
+    /// Same as `run`, with the parameters in an object, which leaves room for new ones.
+    #[rpc(name = "run_v2", params = "raw")]
+    fn run_v2(&self, params: Params) -> BoxFuture<Result<EvmResult>>;
+
+    /// Runs `run_v2` params once and returns the access list of the addresses and
+    /// slots that the execution accessed, like `eth_createAccessList`.
+    #[rpc(name = "create_access_list", params = "raw")]
+    fn create_access_list(&self, params: Params) -> BoxFuture<Result<AccessListResult>>;
+
+    /// Same as `run`, with a geth-style `structLogs` trace of the executed opcodes.
+    #[rpc(name = "trace")]
+    fn trace(
+        &self,
+        address: String,
+        caller: String,
+        code: String,
+        data: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+        overrides: Option<StateOverrides>,
+        config: Option<StructLoggerConfig>,
+    ) -> BoxFuture<Result<EvmResult>>;
+
+    /// Same as `run`, with the tree of calls and creates made by the execution.
+    #[rpc(name = "trace_calls")]
+    fn trace_calls(
+        &self,
+        address: String,
+        caller: String,
+        code: String,
+        data: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+        overrides: Option<StateOverrides>,
+    ) -> BoxFuture<Result<EvmResult>>;
+
+    /// Same as `run`, with the state read by the execution and how it was changed.
+    #[rpc(name = "trace_state")]
+    fn trace_state(
+        &self,
+        address: String,
+        caller: String,
+        code: String,
+        data: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+        overrides: Option<StateOverrides>,
+    ) -> BoxFuture<Result<EvmResult>>;
+
+    /// Read-only execution with the same arguments as `run`, like `eth_call`. Any
+    /// attempt to modify state fails, and the state is never mutated.
+    #[rpc(name = "call")]
+    fn call(
+        &self,
+        address: String,
+        caller: String,
+        code: String,
+        data: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+        overrides: Option<StateOverrides>,
+    ) -> BoxFuture<Result<CallResult>>;
+
+    /// Smallest `gas_limit` (Scilla gas), up to the block gas limit, for which `run` with
+    /// the same arguments succeeds. Never yields state changes.
+    #[rpc(name = "estimate_gas")]
+    fn estimate_gas(
+        &self,
+        address: String,
+        caller: String,
+        code: String,
+        data: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+    ) -> BoxFuture<Result<u64>>;
+
+    /// Deploy a contract by running `code` as init code. The contract address is
+    /// derived from `caller` and its nonce.
+    #[rpc(name = "create")]
+    fn create(
+        &self,
+        caller: String,
+        code: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+    ) -> BoxFuture<Result<EvmResult>>;
+
+    /// Like `create`, but the contract address is derived from `salt` and the init code.
+    #[rpc(name = "create2")]
+    fn create2(
+        &self,
+        caller: String,
+        code: String,
+        salt: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
     ) -> BoxFuture<Result<EvmResult>>;
 }
 
 struct EvmServer {
     tracing: bool,
     backend_config: ScillaBackendConfig,
-    gas_scaling_factor: u64,
+    gas_scaling: GasScaling,
+    fork_schedule: ForkSchedule,
+    recorder: Option<Arc<Recorder>>,
 }
 
 impl Rpc for EvmServer {
@@ -133,196 +496,1337 @@ impl Rpc for EvmServer {
         &self,
         address: String,
         caller: String,
-        code_hex: String,
-        data_hex: String,
+        code: String,
+        data: String,
         apparent_value: String,
         gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+        overrides: Option<StateOverrides>,
     ) -> BoxFuture<Result<EvmResult>> {
-        let backend = ScillaBackend::new(self.backend_config.clone());
-        let tracing = self.tracing;
-        let gas_scaling_factor = self.gas_scaling_factor;
+        self.run_with(RunParams {
+            address,
+            caller,
+            code,
+            data,
+            apparent_value,
+            gas_limit,
+            fork,
+            mode,
+            overrides,
+            ..Default::default()
+        })
+    }
+
+    fn run_v2(&self, params: Params) -> BoxFuture<Result<EvmResult>> {
+        match params.parse() {
+            Ok(params) => self.run_with(params),
+            Err(e) => Box::pin(futures::future::ready(Err(e))),
+        }
+    }
+
+    fn create_access_list(&self, params: Params) -> BoxFuture<Result<AccessListResult>> {
+        let run = params.parse().map(|params| self.run_evm(params));
         Box::pin(async move {
-            run_evm_impl(
-                address,
-                caller,
-                code_hex,
-                data_hex,
-                apparent_value,
-                gas_limit,
-                backend,
-                tracing,
-                gas_scaling_factor,
-            )
-            .await
+            let (result, details) = run?.await?;
+            Ok(AccessListResult {
+                exit_reason: result.exit_reason,
+                revert_reason: result.revert_reason,
+                access_list: details.access_list,
+                gas_used: result.gas_used,
+                evm_gas_used: result.evm_gas_used,
+                evm_gas_saving: details.access_list_gas_saving,
+            })
         })
     }
-}
 
-#[allow(clippy::too_many_arguments)]
-async fn run_evm_impl(
-    address: String,
-    caller: String,
-    code_hex: String,
-    data_hex: String,
-    apparent_value: String,
-    gas_limit: u64,
-    backend: ScillaBackend,
-    tracing: bool,
-    gas_scaling_factor: u64,
-) -> Result<EvmResult> {
-    // We must spawn a separate blocking task (on a blocking thread), because by default a JSONRPC
-    // method runs as a non-blocking thread under a tokio runtime, and creating a new runtime
-    // cannot be done. And we'll need a new runtime that we can safely drop on a handled
-    // panic. (Using the parent runtime and dropping on stack unwind will mess up the parent
-    // runtime).
-    tokio::task::spawn_blocking(move || {
-        let code =
-            Rc::new(hex::decode(&code_hex).map_err(|e| {
-                Error::invalid_params(format!("code: '{}...' {}", &code_hex[..10], e))
-            })?);
-        let data =
-            Rc::new(hex::decode(&data_hex).map_err(|e| {
-                Error::invalid_params(format!("data: '{}...' {}", &data_hex[..10], e))
-            })?);
-
-        let config = evm::Config::london();
-        let apparent_value = U256::from_dec_str(&apparent_value)
-            .map_err(|e| Error::invalid_params(format!("apparent_value: {}", e)))?;
-        let apparent_value = backend.scale_zil_to_eth(apparent_value);
-        let context = evm::Context {
-            address: H160::from_str(&address)
-                .map_err(|e| Error::invalid_params(format!("address: {}", e)))?,
-            caller: H160::from_str(&caller)
-                .map_err(|e| Error::invalid_params(format!("caller: {}", e)))?,
+    fn trace(
+        &self,
+        address: String,
+        caller: String,
+        code: String,
+        data: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+        overrides: Option<StateOverrides>,
+        config: Option<StructLoggerConfig>,
+    ) -> BoxFuture<Result<EvmResult>> {
+        self.run_with(RunParams {
+            address,
+            caller,
+            code,
+            data,
             apparent_value,
-        };
-        let mut runtime = evm::Runtime::new(code, data, context, &config);
-        // Scale the gas limit.
-        let gas_limit = gas_limit * gas_scaling_factor;
-        let metadata = StackSubstateMetadata::new(gas_limit, &config);
-        let state = MemoryStackState::new(metadata, &backend);
-
-        // TODO: implement all precompiles.
-        let precompiles = BTreeMap::from([(
-            H160::from_str("0000000000000000000000000000000000000001").unwrap(),
-            precompiles::ecrecover as PrecompileFn,
-        )]);
+            gas_limit,
+            fork,
+            mode,
+            overrides,
+            tracer: Some(TracerConfig::StructLogger(config.unwrap_or_default())),
+            ..Default::default()
+        })
+    }
 
-        let mut executor =
-            evm::executor::stack::StackExecutor::new_with_precompiles(state, &config, &precompiles);
+    fn trace_calls(
+        &self,
+        address: String,
+        caller: String,
+        code: String,
+        data: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+        overrides: Option<StateOverrides>,
+    ) -> BoxFuture<Result<EvmResult>> {
+        self.run_with(RunParams {
+            address,
+            caller,
+            code,
+            data,
+            apparent_value,
+            gas_limit,
+            fork,
+            mode,
+            overrides,
+            tracer: Some(TracerConfig::CallTracer),
+            ..Default::default()
+        })
+    }
 
-        info!(
-            "Executing runtime with code \"{:?}\" and data \"{:?}\"",
-            code_hex, data_hex,
-        );
-        let mut listener = LoggingEventListener;
+    fn trace_state(
+        &self,
+        address: String,
+        caller: String,
+        code: String,
+        data: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+        overrides: Option<StateOverrides>,
+    ) -> BoxFuture<Result<EvmResult>> {
+        self.run_with(RunParams {
+            address,
+            caller,
+            code,
+            data,
+            apparent_value,
+            gas_limit,
+            fork,
+            mode,
+            overrides,
+            tracer: Some(TracerConfig::PrestateTracer),
+            ..Default::default()
+        })
+    }
 
-        // We have to catch panics, as error handling in the Backend interface of
-        // do not have Result, assuming all operations are successful.
-        //
-        // We are asserting it is safe to unwind, as objects will be dropped after
-        // the unwind.
-        let result = panic::catch_unwind(AssertUnwindSafe(|| {
-            if tracing {
-                evm::tracing::using(&mut listener, || executor.execute(&mut runtime))
-            } else {
-                executor.execute(&mut runtime)
-            }
-        }));
-        // Scale back remaining gas to Scilla units (no rounding!).
-        let remaining_gas = executor.gas() / gas_scaling_factor;
-        match result {
-            Ok(exit_reason) => {
-                info!("Exit: {:?}", exit_reason);
-                let (state_apply, logs) = executor.into_state().deconstruct();
-                info!(
-                    "Return value: {:?}",
-                    hex::encode(runtime.machine().return_value())
-                );
-                Ok(EvmResult {
-                    exit_reason,
-                    return_value: hex::encode(runtime.machine().return_value()),
-                    apply: state_apply
-                        .into_iter()
-                        .map(|apply| match apply {
-                            Apply::Delete { address } => DirtyState(Apply::Delete { address }),
-                            Apply::Modify {
-                                address,
-                                basic,
-                                code,
-                                storage,
-                                reset_storage,
-                            } => DirtyState(Apply::Modify {
-                                address,
-                                basic: Basic {
-                                    balance: backend.scale_eth_to_zil(basic.balance),
-                                    nonce: basic.nonce,
-                                },
-                                code,
-                                storage: storage
-                                    .into_iter()
-                                    .map(|(k, v)| backend.encode_storage(k, v))
-                                    .collect(),
-                                reset_storage,
-                            }),
-                        })
-                        .collect(),
-                    logs: logs.into_iter().collect(),
-                    remaining_gas,
-                })
-            }
-            Err(panic) => {
-                let panic_message = panic
-                    .downcast::<String>()
-                    .unwrap_or(Box::new("unknown panic".to_string()));
-                error!("EVM panicked: '{:?}'", panic_message);
-                Ok(EvmResult {
-                    exit_reason: evm::ExitReason::Fatal(evm::ExitFatal::Other(
-                        format!("EVM execution failed: '{:?}'", panic_message).into(),
-                    )),
-                    return_value: "".to_string(),
-                    apply: vec![],
-                    logs: vec![], // TODO: shouldn't we get the logs here too?
-                    remaining_gas,
-                })
+    fn call(
+        &self,
+        address: String,
+        caller: String,
+        code: String,
+        data: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+        overrides: Option<StateOverrides>,
+    ) -> BoxFuture<Result<CallResult>> {
+        let result = self.run_with(RunParams {
+            address,
+            caller,
+            code,
+            data,
+            apparent_value,
+            gas_limit,
+            fork,
+            mode,
+            overrides,
+            is_static: true,
+            ..Default::default()
+        });
+        Box::pin(async move {
+            let result = result.await?;
+            // There is no `error` field in a `CallResult` to report it.
+            if let Some(error) = result.error {
+                return Err(error.into());
             }
-        }
-    })
-    .await
-    .unwrap()
-}
+            // Whatever the execution might have changed is dropped here.
+            Ok(CallResult {
+                exit_reason: result.exit_reason,
+                return_value: result.return_value,
+                revert_reason: result.revert_reason,
+                gas_used: result.gas_used,
+                evm_gas_used: result.evm_gas_used,
+            })
+        })
+    }
 
-struct LoggingEventListener;
+    fn estimate_gas(
+        &self,
+        address: String,
+        caller: String,
+        code_hex: String,
+        data_hex: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+        mode: Option<String>,
+    ) -> BoxFuture<Result<u64>> {
+        let request = RecordedRequest::EstimateGas(RunParams {
+            address: address.clone(),
+            caller: caller.clone(),
+            code: code_hex.clone(),
+            data: data_hex.clone(),
+            apparent_value: apparent_value.clone(),
+            gas_limit,
+            fork: fork.clone(),
+            mode: mode.clone(),
+            ..Default::default()
+        });
+        let gas_scaling = self.gas_scaling;
+        let fork_schedule = self.fork_schedule.clone();
+        Box::pin(self.execute(
+            request,
+            move |backend| {
+                estimate_gas_impl(
+                    address,
+                    caller,
+                    code_hex,
+                    data_hex,
+                    apparent_value,
+                    gas_limit,
+                    backend,
+                    gas_scaling,
+                    fork,
+                    fork_schedule,
+                    mode,
+                )
+            },
+            |gas: &u64| Value::from(*gas),
+        ))
+    }
 
-impl tracing::EventListener for LoggingEventListener {
-    fn event(&mut self, event: tracing::Event) {
-        debug!("EVM Event {:?}", event);
+    fn create(
+        &self,
+        caller: String,
+        code_hex: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+    ) -> BoxFuture<Result<EvmResult>> {
+        self.create_with_salt(caller, code_hex, None, apparent_value, gas_limit, fork)
+    }
+
+    fn create2(
+        &self,
+        caller: String,
+        code_hex: String,
+        salt: String,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+    ) -> BoxFuture<Result<EvmResult>> {
+        self.create_with_salt(
+            caller,
+            code_hex,
+            Some(salt),
+            apparent_value,
+            gas_limit,
+            fork,
+        )
     }
 }
 
-fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+impl EvmServer {
+    fn run_with(&self, params: RunParams) -> BoxFuture<Result<EvmResult>> {
+        let run = self.run_evm(params);
+        Box::pin(async move { Ok(run.await?.0) })
+    }
 
-    match args.log4rs {
-        Some(log_config) if log_config != "" => {
-            log4rs::init_file(log_config, Default::default()).unwrap();
-        }
-        _ => {
-            let config_str = include_str!("../log4rs-local.yml");
-            let config = serde_yaml::from_str(config_str).unwrap();
+    fn run_evm(
+        &self,
+        params: RunParams,
+    ) -> impl Future<Output = Result<(EvmResult, ExecutionDetails)>> {
+        let tracing = self.tracing;
+        let gas_scaling = self.gas_scaling;
+        let fork_schedule = self.fork_schedule.clone();
+        self.execute(
+            RecordedRequest::Run(params.clone()),
+            move |backend| run_evm_impl(params, backend, tracing, gas_scaling, fork_schedule),
+            |(result, _): &(EvmResult, ExecutionDetails)| {
+                serde_json::to_value(result).unwrap_or_default()
+            },
+        )
+    }
+
+    /// Starts `execution` on a new backend, and records it as an execution of `request`,
+    /// with its outcome converted by `to_value`, if `--record` is set.
+    fn execute<T, F>(
+        &self,
+        request: RecordedRequest,
+        execution: impl FnOnce(ScillaBackend) -> F,
+        to_value: fn(&T) -> Value,
+    ) -> impl Future<Output = Result<T>>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let backend = ScillaBackend::new(self.backend_config.clone());
+        let queries = Arc::new(Mutex::new(vec![]));
+        let (backend, recorded) = match &self.recorder {
+            Some(recorder) => (
+                backend.with_recording(queries.clone()),
+                Some((recorder.clone(), request)),
+            ),
+            None => (backend, None),
+        };
+        let execution = execution(backend);
+        async move {
+            let result = execution.await;
+            if let Some((recorder, request)) = recorded {
+                recorder.record(&RecordedRun {
+                    request,
+                    queries: std::mem::take(&mut *queries.lock().unwrap()),
+                    result: result.as_ref().map(to_value).map_err(Error::clone),
+                });
+            }
+            result
+        }
+    }
+
+    fn create_with_salt(
+        &self,
+        caller: String,
+        code_hex: String,
+        salt: Option<String>,
+        apparent_value: String,
+        gas_limit: u64,
+        fork: Option<String>,
+    ) -> BoxFuture<Result<EvmResult>> {
+        let request = RecordedRequest::Create(CreateParams {
+            caller: caller.clone(),
+            code: code_hex.clone(),
+            salt: salt.clone(),
+            apparent_value: apparent_value.clone(),
+            gas_limit,
+            fork: fork.clone(),
+        });
+        let tracing = self.tracing;
+        let gas_scaling = self.gas_scaling;
+        let fork_schedule = self.fork_schedule.clone();
+        let create = self.execute(
+            request,
+            move |backend| {
+                create_impl(
+                    caller,
+                    code_hex,
+                    apparent_value,
+                    gas_limit,
+                    salt,
+                    backend,
+                    tracing,
+                    gas_scaling,
+                    fork,
+                    fork_schedule,
+                )
+            },
+            |(result, _): &(EvmResult, Vec<Apply<BTreeMap<H256, H256>>>)| {
+                serde_json::to_value(result).unwrap_or_default()
+            },
+        );
+        Box::pin(async move { Ok(create.await?.0) })
+    }
+}
+
+type Precompiles = BTreeMap<H160, PrecompileFn>;
+
+type Executor<'a, B> = StackExecutor<'a, 'a, MemoryStackState<'a, 'a, B>, Precompiles>;
+
+/// Decoded parameters of an execution request.
+struct EvmInput {
+    context: evm::Context,
+    code: Rc<Vec<u8>>,
+    data: Rc<Vec<u8>>,
+    access_list: Vec<AccessListItem>,
+}
+
+impl EvmInput {
+    fn parse(
+        address: &str,
+        caller: &str,
+        code_hex: &str,
+        data_hex: &str,
+        apparent_value: &str,
+        access_list: Option<Vec<AccessListItem>>,
+        backend: &impl ZilliqaBackend,
+    ) -> Result<Self> {
+        let code = Rc::new(parse_hex("code", code_hex)?);
+        let data = Rc::new(parse_hex("data", data_hex)?);
+        let context = evm::Context {
+            address: parse_address("address", address)?,
+            caller: parse_address("caller", caller)?,
+            apparent_value: parse_value(apparent_value, backend)?,
+        };
+        Ok(Self {
+            context,
+            code,
+            data,
+            access_list: access_list.unwrap_or_default(),
+        })
+    }
+
+    /// Addresses warm from the start of a transaction (EIP-2929): the caller, the callee
+    /// and the precompiles.
+    fn warm_addresses(&self, precompiles: &Precompiles) -> BTreeSet<H160> {
+        [self.context.caller, self.context.address]
+            .into_iter()
+            .chain(precompiles.keys().copied())
+            .collect()
+    }
+
+    /// Marks the warm addresses and the access list as accessed before the execution.
+    /// `transact_call` does it as well, but a raw execution would start all cold.
+    fn warm_up<B: Backend>(&self, executor: &mut Executor<B>, warm: &BTreeSet<H160>) {
+        access_list::warm_up(executor.state_mut().metadata_mut(), warm, &self.access_list);
+    }
+}
+
+fn parse_hex(name: &str, value: &str) -> Result<Vec<u8>> {
+    hex::decode(value).map_err(|e| {
+        let prefix: String = value.chars().take(10).collect();
+        Error::invalid_params(format!("{}: '{}...' {}", name, prefix, e))
+    })
+}
+
+fn parse_address(name: &str, value: &str) -> Result<H160> {
+    H160::from_str(value).map_err(|e| Error::invalid_params(format!("{}: {}", name, e)))
+}
+
+fn parse_h256(name: &str, value: &str) -> Result<H256> {
+    H256::from_str(value).map_err(|e| Error::invalid_params(format!("{}: {}", name, e)))
+}
+
+/// Parses message funds given in Zil units and scales them to Eth units.
+fn parse_value(apparent_value: &str, backend: &impl ZilliqaBackend) -> Result<U256> {
+    let apparent_value = U256::from_dec_str(apparent_value)
+        .map_err(|e| Error::invalid_params(format!("apparent_value: {}", e)))?;
+    Ok(backend.scale_zil_to_eth(apparent_value))
+}
+
+/// Scales a gas limit given in Scilla gas to EVM gas.
+fn scale_gas_limit(gas_limit: u64, gas_scaling: GasScaling) -> Result<u64> {
+    gas_scaling
+        .to_evm(gas_limit)
+        .ok_or_else(|| Error::invalid_params(format!("gas_limit {} is too large", gas_limit)))
+}
+
+/// An explicitly requested fork wins, otherwise follow the schedule.
+fn resolve_fork<B: Backend>(
+    fork: Option<String>,
+    fork_schedule: &ForkSchedule,
+    backend: &B,
+) -> Result<Fork> {
+    let fork = match fork {
+        Some(fork) => Fork::from_str(&fork).map_err(Error::invalid_params)?,
+        None if fork_schedule.is_fixed() => fork_schedule.fork_at(U256::zero()),
+        None => {
+            let block_number = panic::catch_unwind(AssertUnwindSafe(|| backend.block_number()))
+                .map_err(caught_panic)?;
+            fork_schedule.fork_at(block_number)
+        }
+    };
+    debug!("Using fork {:?}", fork);
+    Ok(fork)
+}
+
+/// A fresh executor over `backend`. `gas_limit` is in EVM gas units. In a static
+/// executor, all state modifications (SSTORE, LOG, CREATE, SELFDESTRUCT and value
+/// transfers by CALL) fail, including in nested calls.
+fn new_executor<'a, B: Backend>(
+    backend: &'a B,
+    config: &'a evm::Config,
+    precompiles: &'a Precompiles,
+    gas_limit: u64,
+    is_static: bool,
+) -> Executor<'a, B> {
+    let mut metadata = StackSubstateMetadata::new(gas_limit, config);
+    if is_static {
+        metadata = metadata.spit_child(gas_limit, true);
+    }
+    let state = MemoryStackState::new(metadata, backend);
+    StackExecutor::new_with_precompiles(state, config, precompiles)
+}
+
+/// How a message call to an existing contract is executed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ExecutionMode {
+    /// Run the given code directly in an `evm::Runtime`. There is no intrinsic gas,
+    /// no value transfer, no nonce bump and no gas refund. This is the default, as
+    /// the Zilliqa node does all of that itself.
+    Raw,
+    /// A full Ethereum message call via `StackExecutor::transact_call`. The code is
+    /// the one stored at the callee address.
+    Transaction,
+}
+
+impl FromStr for ExecutionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(ExecutionMode::Raw),
+            "transaction" => Ok(ExecutionMode::Transaction),
+            _ => Err(format!("unknown mode '{}'", s)),
+        }
+    }
+}
+
+impl ExecutionMode {
+    fn parse(mode: Option<String>) -> Result<Self> {
+        mode.map_or(Ok(ExecutionMode::Raw), |mode| {
+            mode.parse().map_err(Error::invalid_params)
+        })
+    }
+
+    /// Executes `input` with `gas_limit` (in EVM gas), returning the exit reason and the
+    /// return value.
+    fn execute<B: Backend>(
+        self,
+        executor: &mut Executor<B>,
+        input: &EvmInput,
+        config: &evm::Config,
+        gas_limit: u64,
+    ) -> (evm::ExitReason, Vec<u8>) {
+        match self {
+            ExecutionMode::Raw => {
+                let mut runtime = evm::Runtime::new(
+                    input.code.clone(),
+                    input.data.clone(),
+                    input.context.clone(),
+                    config,
+                );
+                let exit_reason = executor.execute(&mut runtime);
+                (exit_reason, runtime.machine().return_value())
+            }
+            ExecutionMode::Transaction => executor.transact_call(
+                input.context.caller,
+                input.context.address,
+                input.context.apparent_value,
+                input.data.to_vec(),
+                gas_limit,
+                access_list::to_pairs(&input.access_list),
+            ),
+        }
+    }
+
+    /// Gas (in EVM gas) left after the execution. Only transactions get refunds.
+    fn remaining_gas<B: Backend>(self, executor: &Executor<B>, gas_limit: u64) -> u64 {
+        match self {
+            ExecutionMode::Raw => executor.gas(),
+            ExecutionMode::Transaction => gas_limit.saturating_sub(executor.used_gas()),
+        }
+    }
+}
+
+async fn run_evm_impl<B: ZilliqaBackend + Send + 'static>(
+    params: RunParams,
+    backend: B,
+    tracing: bool,
+    gas_scaling: GasScaling,
+    fork_schedule: ForkSchedule,
+) -> Result<(EvmResult, ExecutionDetails)> {
+    // We must spawn a separate blocking task (on a blocking thread), because by default a JSONRPC
+    // method runs as a non-blocking thread under a tokio runtime, and creating a new runtime
+    // cannot be done. And we'll need a new runtime that we can safely drop on a handled
+    // panic. (Using the parent runtime and dropping on stack unwind will mess up the parent
+    // runtime).
+    tokio::task::spawn_blocking(move || {
+        let start = Instant::now();
+        let RunParams {
+            address,
+            caller,
+            code: code_hex,
+            data: data_hex,
+            apparent_value,
+            gas_limit,
+            fork,
+            mode,
+            overrides,
+            origin,
+            gas_price,
+            block_overrides,
+            access_list,
+            is_static,
+            tracer,
+        } = params;
+        let input = EvmInput::parse(
+            &address,
+            &caller,
+            &code_hex,
+            &data_hex,
+            &apparent_value,
+            access_list,
+            &backend,
+        )?;
+        if is_static && !input.context.apparent_value.is_zero() {
+            return Err(Error::invalid_params(
+                "apparent_value must be 0 in a static execution",
+            ));
+        }
+        let mode = ExecutionMode::parse(mode)?;
+        let gas_limit = scale_gas_limit(gas_limit, gas_scaling)?;
+        let env = EnvOverrides {
+            origin,
+            gas_price,
+            block: block_overrides.unwrap_or_default(),
+        };
+        let overridden =
+            matches!(&overrides, Some(overrides) if !overrides.is_empty()) || !env.is_empty();
+        let state_backend =
+            OverrideBackend::new(&backend, overrides.unwrap_or_default(), env, |balance| {
+                backend.scale_zil_to_eth(balance)
+            })
+            .map_err(|e| Error::invalid_params(format!("overrides: {}", e)))?;
+        // The block number may be overridden.
+        let fork = resolve_fork(fork, &fork_schedule, &state_backend)?;
+        let config = fork.config();
+        let precompiles = fork.precompiles();
+        let tracer = tracer.map(TracerConfig::build);
+        let state_backend = TracingBackend::new(&state_backend, tracer.clone());
+        let mut executor =
+            new_executor(&state_backend, &config, &precompiles, gas_limit, is_static);
+        let warm = input.warm_addresses(&precompiles);
+        input.warm_up(&mut executor, &warm);
+
+        info!(
+            "Executing runtime ({:?}) with code \"{:?}\" and data \"{:?}\"",
+            mode, code_hex, data_hex,
+        );
+        let mut listener = LoggingEventListener;
+        let depth = executor.state().metadata().depth();
+
+        // We have to catch panics, as error handling in the Backend interface of
+        // do not have Result, assuming all operations are successful.
+        //
+        // We are asserting it is safe to unwind, as objects will be dropped after
+        // the unwind.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| match &tracer {
+            Some(tracer) => execute_traced(
+                tracer,
+                mode,
+                &mut executor,
+                &input,
+                &config,
+                gas_limit,
+                is_static,
+            ),
+            None if tracing => evm::tracing::using(&mut listener, || {
+                mode.execute(&mut executor, &input, &config, gas_limit)
+            }),
+            None => mode.execute(&mut executor, &input, &config, gas_limit),
+        }));
+        let panicked = result.is_err();
+        if panicked {
+            exit_substates(&mut executor, depth);
+        }
+        let remaining_gas = mode.remaining_gas(&executor, gas_limit);
+        let access_list = match executor.state().metadata().accessed() {
+            Some(accessed) => access_list::from_accessed(accessed, &warm),
+            None => vec![],
+        };
+        // Only a transaction is charged the intrinsic cost of an access list.
+        let access_list_gas_saving = access_list::gas_saving(
+            &access_list,
+            &warm,
+            &config,
+            mode == ExecutionMode::Transaction,
+        );
+        let (mut result, mut state_apply) = build_result(
+            result,
+            executor,
+            &backend,
+            gas_limit,
+            remaining_gas,
+            gas_scaling,
+            tracer.as_ref(),
+        );
+        // Not even the nonce of the caller changes in a static execution. The changes of
+        // an execution against overridden state or environment are a simulation, not to be
+        // committed.
+        if is_static || overridden {
+            result.apply.clear();
+            state_apply.clear();
+        }
+        if panicked {
+            count_caught_panic(&result);
+        }
+        metrics::observe_execution(
+            "run",
+            &result.exit_reason,
+            start.elapsed(),
+            result.evm_gas_used,
+        );
+        Ok((
+            result,
+            ExecutionDetails {
+                state_apply,
+                access_list,
+                access_list_gas_saving,
+            },
+        ))
+    })
+    .await
+    .unwrap()
+}
+
+/// Like `ExecutionMode::execute`, with all events passed to `tracer`. A raw execution
+/// does not go through `StackExecutor::call`, so its frame is reported here.
+fn execute_traced<B: Backend>(
+    tracer: &SharedTracer,
+    mode: ExecutionMode,
+    executor: &mut Executor<B>,
+    input: &EvmInput,
+    config: &evm::Config,
+    gas_limit: u64,
+    is_static: bool,
+) -> (evm::ExitReason, Vec<u8>) {
+    tracers::using(tracer, || {
+        if mode == ExecutionMode::Raw {
+            tracer.borrow_mut().evm_event(tracing::Event::Call {
+                code_address: input.context.address,
+                transfer: &None,
+                input: input.data.as_slice(),
+                target_gas: Some(gas_limit),
+                is_static,
+                context: &input.context,
+            });
+        }
+        let (exit_reason, return_value) = mode.execute(executor, input, config, gas_limit);
+        if mode == ExecutionMode::Raw {
+            tracer.borrow_mut().evm_event(tracing::Event::Exit {
+                reason: &exit_reason,
+                return_value: &return_value,
+            });
+        }
+        (exit_reason, return_value)
+    })
+}
+
+/// Like `run_evm_impl`, for the creation of a contract. The state changes of the result
+/// are also returned in EVM units.
+#[allow(clippy::too_many_arguments)]
+async fn create_impl<B: ZilliqaBackend + Send + 'static>(
+    caller: String,
+    code_hex: String,
+    apparent_value: String,
+    gas_limit: u64,
+    salt: Option<String>,
+    backend: B,
+    tracing: bool,
+    gas_scaling: GasScaling,
+    fork: Option<String>,
+    fork_schedule: ForkSchedule,
+) -> Result<(EvmResult, Vec<Apply<BTreeMap<H256, H256>>>)> {
+    // See run_evm_impl on why this runs on a blocking thread.
+    tokio::task::spawn_blocking(move || {
+        let start = Instant::now();
+        let caller = parse_address("caller", &caller)?;
+        let init_code = parse_hex("code", &code_hex)?;
+        let value = parse_value(&apparent_value, &backend)?;
+        let salt = salt.map(|salt| parse_h256("salt", &salt)).transpose()?;
+        let fork = resolve_fork(fork, &fork_schedule, &backend)?;
+        let config = fork.config();
+        let precompiles = fork.precompiles();
+        let gas_limit = scale_gas_limit(gas_limit, gas_scaling)?;
+        let mut executor = new_executor(&backend, &config, &precompiles, gas_limit, false);
+
+        info!("Creating contract with init code \"{:?}\"", code_hex);
+        let mut listener = LoggingEventListener;
+        let depth = executor.state().metadata().depth();
+
+        // See run_evm_impl on catching panics.
+        let mut contract_address = None;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let scheme = match salt {
+                None => evm::CreateScheme::Legacy { caller },
+                Some(salt) => evm::CreateScheme::Create2 {
+                    caller,
+                    code_hash: H256::from_slice(&sha3::Keccak256::digest(&init_code)),
+                    salt,
+                },
+            };
+            // Must be derived before the nonce of the caller is bumped by the creation.
+            contract_address = Some(executor.create_address(scheme));
+            let transact = || match salt {
+                None => executor.transact_create(caller, value, init_code, gas_limit, vec![]),
+                Some(salt) => {
+                    executor.transact_create2(caller, value, init_code, salt, gas_limit, vec![])
+                }
+            };
+            if tracing {
+                evm::tracing::using(&mut listener, transact)
+            } else {
+                transact()
+            }
+        }));
+        let panicked = result.is_err();
+        if panicked {
+            exit_substates(&mut executor, depth);
+        }
+        let remaining_gas = ExecutionMode::Transaction.remaining_gas(&executor, gas_limit);
+        let (mut evm_result, state_apply) = build_result(
+            result,
+            executor,
+            &backend,
+            gas_limit,
+            remaining_gas,
+            gas_scaling,
+            None,
+        );
+        if panicked {
+            count_caught_panic(&evm_result);
+        }
+        metrics::observe_execution(
+            "create",
+            &evm_result.exit_reason,
+            start.elapsed(),
+            evm_result.evm_gas_used,
+        );
+        if evm_result.exit_reason.is_succeed() {
+            info!("Created contract at {:?}", contract_address);
+            evm_result.contract_address = contract_address;
+        }
+        Ok((evm_result, state_apply))
+    })
+    .await
+    .unwrap()
+}
+
+/// Turns the outcome of an execution into an `EvmResult`, converting the state
+/// changes collected by the executor and `remaining_gas` to Zilliqa units. The
+/// trace of `tracer`, if any, is added to the result. The state changes of its
+/// `apply` are also returned in EVM units.
+fn build_result<B: Backend>(
+    result: std::thread::Result<(evm::ExitReason, Vec<u8>)>,
+    executor: Executor<B>,
+    backend: &impl ZilliqaBackend,
+    gas_limit: u64,
+    remaining_gas: u64,
+    gas_scaling: GasScaling,
+    tracer: Option<&SharedTracer>,
+) -> (EvmResult, Vec<Apply<BTreeMap<H256, H256>>>) {
+    let gas = gas_scaling.usage(gas_limit, remaining_gas);
+    let trace = |exit_reason: &evm::ExitReason,
+                 return_value: &[u8],
+                 apply: &[Apply<BTreeMap<H256, H256>>]| {
+        tracer.map(|tracer| {
+            tracer.borrow().result(&TraceOutcome {
+                exit_reason,
+                return_value,
+                used_gas: gas.evm_used,
+                apply,
+            })
+        })
+    };
+    match result {
+        Ok((exit_reason, return_value)) => {
+            info!("Exit: {:?}", exit_reason);
+            let (state_apply, logs) = executor.into_state().deconstruct();
+            info!("Return value: {:?}", hex::encode(&return_value));
+            // Collected first, so that the tracer sees the changes in EVM units.
+            let state_apply = collect_apply(state_apply);
+            let trace = trace(&exit_reason, &return_value, &state_apply);
+            let error = match &exit_reason {
+                evm::ExitReason::Fatal(fatal) => Some(EvmError::EvmFatal(format!("{:?}", fatal))),
+                _ => None,
+            };
+            let revert_reason = match &exit_reason {
+                evm::ExitReason::Revert(_) => decode_revert_reason(&return_value),
+                _ => None,
+            };
+            let apply = match dirty_state(&state_apply, backend, backend.precision_policy()) {
+                Ok(apply) => apply,
+                Err(e) => {
+                    info!("Rejected: {}", e);
+                    let result = EvmResult {
+                        exit_reason: evm::ExitReason::Error(evm::ExitError::Other(e.into())),
+                        return_value: "".to_string(),
+                        revert_reason: None,
+                        apply: vec![],
+                        logs: vec![],
+                        remaining_gas: gas.remaining,
+                        gas_used: gas.used,
+                        evm_gas_used: gas.evm_used,
+                        contract_address: None,
+                        error: None,
+                        trace,
+                        partial: None,
+                    };
+                    return (result, vec![]);
+                }
+            };
+            let result = EvmResult {
+                exit_reason,
+                return_value: hex::encode(return_value),
+                revert_reason,
+                apply,
+                logs: logs.into_iter().collect(),
+                remaining_gas: gas.remaining,
+                gas_used: gas.used,
+                evm_gas_used: gas.evm_used,
+                contract_address: None,
+                error,
+                trace,
+                partial: None,
+            };
+            (result, state_apply)
+        }
+        Err(panic) => {
+            let error = EvmError::from_panic(panic);
+            let exit_reason =
+                evm::ExitReason::Fatal(evm::ExitFatal::Other(error.to_string().into()));
+            // Collecting the state may need the backend again, which may fail again.
+            let partial = panic::catch_unwind(AssertUnwindSafe(|| {
+                let (state_apply, logs) = executor.into_state().deconstruct();
+                (
+                    collect_apply(state_apply),
+                    logs.into_iter().collect::<Vec<_>>(),
+                )
+            }));
+            let (state_apply, logs) = partial.unwrap_or_else(|panic| {
+                info!("No partial state: {}", EvmError::from_panic(panic));
+                (vec![], vec![])
+            });
+            let trace = trace(&exit_reason, &[], &state_apply);
+            let result = EvmResult {
+                exit_reason,
+                return_value: "".to_string(),
+                revert_reason: None,
+                apply: vec![],
+                logs: vec![],
+                remaining_gas: gas.remaining,
+                gas_used: gas.used,
+                evm_gas_used: gas.evm_used,
+                contract_address: None,
+                error: Some(error),
+                trace,
+                partial: Some(PartialResult {
+                    // Nothing is applied, so nothing is lost with the dust.
+                    apply: dirty_state(&state_apply, backend, PrecisionPolicy::Dust)
+                        .unwrap_or_default(),
+                    logs,
+                    gas_used: gas.used,
+                }),
+            };
+            (result, vec![])
+        }
+    }
+}
+
+/// Counts the panic that aborted the execution of `result`, by its error. Only the
+/// executions count them, so that a panic is counted once.
+fn count_caught_panic(result: &EvmResult) {
+    if let Some(error) = &result.error {
+        metrics::count_caught_panic(error);
+    }
+}
+
+/// The error behind a panic caught outside of `run_evm_impl` and `create_impl`, counted
+/// as they count theirs.
+fn caught_panic(panic: Box<dyn std::any::Any + Send>) -> EvmError {
+    let error = EvmError::from_panic(panic);
+    metrics::count_caught_panic(&error);
+    error
+}
+
+/// The state changes of `MemoryStackState::deconstruct`, with the storage collected.
+fn collect_apply<I: IntoIterator<Item = (H256, H256)>>(
+    state_apply: impl IntoIterator<Item = Apply<I>>,
+) -> Vec<Apply<BTreeMap<H256, H256>>> {
+    state_apply
+        .into_iter()
+        .map(|apply| match apply {
+            Apply::Delete { address } => Apply::Delete { address },
+            Apply::Modify {
+                address,
+                basic,
+                code,
+                storage,
+                reset_storage,
+            } => Apply::Modify {
+                address,
+                basic,
+                code,
+                storage: storage.into_iter().collect(),
+                reset_storage,
+            },
+        })
+        .collect()
+}
+
+/// Converts state changes to what the node expects: balances in Zil units and storage
+/// encoded for Scilla. Balances that are not a whole number of Zils are handled
+/// according to `precision_policy`.
+fn dirty_state(
+    state_apply: &[Apply<BTreeMap<H256, H256>>],
+    backend: &impl ZilliqaBackend,
+    precision_policy: PrecisionPolicy,
+) -> std::result::Result<Vec<DirtyState>, String> {
+    state_apply
+        .iter()
+        .map(|apply| match apply {
+            Apply::Delete { address } => Ok(DirtyState(Apply::Delete { address: *address }, None)),
+            Apply::Modify {
+                address,
+                basic,
+                code,
+                storage,
+                reset_storage,
+            } => {
+                let (balance, dust) = backend.split_eth_to_zil(basic.balance);
+                let dust = match precision_policy {
+                    _ if dust.is_zero() => None,
+                    PrecisionPolicy::Truncate => None,
+                    PrecisionPolicy::Reject => {
+                        return Err(format!(
+                            "balance of {:?} is not a whole number of Zils: {} wei",
+                            address, basic.balance
+                        ))
+                    }
+                    PrecisionPolicy::Dust => Some(dust),
+                };
+                let apply = Apply::Modify {
+                    address: *address,
+                    basic: Basic {
+                        balance,
+                        nonce: basic.nonce,
+                    },
+                    code: code.clone(),
+                    storage: storage
+                        .iter()
+                        .map(|(k, v)| scillabackend::encode_storage(*k, *v))
+                        .collect(),
+                    reset_storage: *reset_storage,
+                };
+                Ok(DirtyState(apply, dust))
+            }
+        })
+        .collect()
+}
+
+/// After a panic, commits the substates of the calls that were in progress, down to
+/// `depth`, so that the state and the gas used so far can be collected.
+fn exit_substates<B: Backend>(executor: &mut Executor<B>, depth: Option<usize>) {
+    while executor.state().metadata().depth() > depth {
+        if let Err(e) = executor.state_mut().exit_commit() {
+            info!("Cannot exit substate: {:?}", e);
+            break;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn estimate_gas_impl<B: ZilliqaBackend + Send + 'static>(
+    address: String,
+    caller: String,
+    code_hex: String,
+    data_hex: String,
+    apparent_value: String,
+    gas_limit: u64,
+    backend: B,
+    gas_scaling: GasScaling,
+    fork: Option<String>,
+    fork_schedule: ForkSchedule,
+    mode: Option<String>,
+) -> Result<u64> {
+    let start = Instant::now();
+    // See run_evm_impl on why this runs on a blocking thread.
+    let estimate = tokio::task::spawn_blocking(move || {
+        let input = EvmInput::parse(
+            &address,
+            &caller,
+            &code_hex,
+            &data_hex,
+            &apparent_value,
+            None,
+            &backend,
+        )?;
+        let mode = ExecutionMode::parse(mode)?;
+        let fork = resolve_fork(fork, &fork_schedule, &backend)?;
+        let config = fork.config();
+        let precompiles = fork.precompiles();
+        let warm = input.warm_addresses(&precompiles);
+        // No execution can use more than the block, so neither does the search. A block
+        // gas limit of zero is unknown to the node.
+        let block_gas_limit = panic::catch_unwind(AssertUnwindSafe(|| backend.block_gas_limit()))
+            .map_err(caught_panic)?;
+        let gas_limit = if block_gas_limit.is_zero() {
+            gas_limit
+        } else {
+            let block_gas_limit = block_gas_limit.min(U256::from(u64::MAX)).as_u64();
+            gas_limit.min(gas_scaling.limit_to_scilla(block_gas_limit))
+        };
+
+        // Execute from scratch with `scilla_gas`, returning the exit reason, the return
+        // value and the Scilla gas used. The state of the executor is dropped.
+        let execute = |scilla_gas: u64| -> Result<(evm::ExitReason, Vec<u8>, u64)> {
+            let start = Instant::now();
+            let gas_limit = scale_gas_limit(scilla_gas, gas_scaling)?;
+            let mut executor = new_executor(&backend, &config, &precompiles, gas_limit, false);
+            input.warm_up(&mut executor, &warm);
+            let (exit_reason, return_value) = panic::catch_unwind(AssertUnwindSafe(|| {
+                mode.execute(&mut executor, &input, &config, gas_limit)
+            }))
+            .map_err(caught_panic)?;
+            let remaining_gas = mode.remaining_gas(&executor, gas_limit);
+            let usage = gas_scaling.usage(gas_limit, remaining_gas);
+            metrics::observe_execution(
+                "estimate_gas",
+                &exit_reason,
+                start.elapsed(),
+                usage.evm_used,
+            );
+            Ok((exit_reason, return_value, usage.used))
+        };
+
+        // If it fails with everything we've got, there is nothing to search for.
+        let (exit_reason, return_value, used_gas) = execute(gas_limit)?;
+        if !exit_reason.is_succeed() {
+            return Err(estimate_gas_error(&exit_reason, &return_value, gas_limit));
+        }
+
+        // Less than what was used is never enough. Search in (lo, hi], with `hi` succeeding.
+        let (mut lo, mut hi) = (used_gas.saturating_sub(1), gas_limit);
+        if used_gas == 0 {
+            hi = 0;
+        }
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if execute(mid)?.0.is_succeed() {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        info!("Estimated gas: {}", hi);
+        Ok(hi)
+    })
+    .await
+    .unwrap();
+    metrics::observe_gas_estimate(start.elapsed());
+    estimate
+}
+
+/// Error reported when an execution fails even with the whole gas limit, capped by the
+/// block gas limit.
+fn estimate_gas_error(exit_reason: &evm::ExitReason, return_value: &[u8], gas_limit: u64) -> Error {
+    match exit_reason {
+        evm::ExitReason::Revert(_) => Error {
+            code: ErrorCode::ServerError(3),
+            message: match decode_revert_reason(return_value) {
+                Some(reason) => format!("execution reverted: {}", reason),
+                None => "execution reverted".to_string(),
+            },
+            data: Some(Value::String(hex::encode(return_value))),
+        },
+        _ => Error {
+            code: ErrorCode::ServerError(-32000),
+            message: format!(
+                "gas required exceeds allowance ({}): {:?}",
+                gas_limit, exit_reason
+            ),
+            data: None,
+        },
+    }
+}
+
+struct LoggingEventListener;
+
+impl tracing::EventListener for LoggingEventListener {
+    fn event(&mut self, event: tracing::Event) {
+        debug!("EVM Event {:?}", event);
+    }
+}
+
+/// Runs the `exec` command against the state of its JSON file, and prints the result as
+/// `run` would return it.
+fn exec(
+    args: ExecArgs,
+    tracing: bool,
+    zil_scaling_factor: u64,
+    precision_policy: PrecisionPolicy,
+    gas_scaling: GasScaling,
+    fork_schedule: ForkSchedule,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let state = match &args.state {
+        Some(path) => MemoryState::load(path)?,
+        None => MemoryState::default(),
+    };
+    let backend = InMemoryBackend::new(state, zil_scaling_factor, precision_policy);
+    let mut post_backend = backend.clone();
+    let params = RunParams {
+        address: args.address,
+        caller: args.caller,
+        code: args.code,
+        data: args.data,
+        apparent_value: args.value,
+        gas_limit: args.gas_limit,
+        mode: args.mode,
+        tracer: args.tracer,
+        ..Default::default()
+    };
+    let runtime = tokio::runtime::Runtime::new()?;
+    let (result, details) = runtime.block_on(run_evm_impl(
+        params,
+        backend,
+        tracing,
+        gas_scaling,
+        fork_schedule,
+    ))?;
+    println!("{}", serde_json::to_string_pretty(&result)?);
+    if let Some(path) = args.dump {
+        if result.exit_reason.is_succeed() {
+            post_backend.apply(details.state_apply);
+        }
+        post_backend.state().dump(&path)?;
+    }
+    Ok(())
+}
+
+/// Runs the `replay` command: executes the executions of a recording again, with the queries
+/// to the node answered from the recording, and prints how the results differ.
+fn replay(
+    args: ReplayArgs,
+    tracing: bool,
+    backend_config: ScillaBackendConfig,
+    gas_scaling: GasScaling,
+    fork_schedule: ForkSchedule,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let runs: Vec<RecordedRun> = recording::read(&args.recording)?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    let mut mismatches = 0;
+    for (index, run) in runs.iter().enumerate() {
+        let backend = ScillaBackend::replaying(backend_config.clone(), run.queries.clone());
+        let fork_schedule = fork_schedule.clone();
+        let result = match run.request.clone() {
+            RecordedRequest::Run(params) => runtime
+                .block_on(run_evm_impl(
+                    params,
+                    backend,
+                    tracing,
+                    gas_scaling,
+                    fork_schedule,
+                ))
+                .map(|(result, _)| serde_json::to_value(&result).unwrap_or_default()),
+            RecordedRequest::Create(params) => runtime
+                .block_on(create_impl(
+                    params.caller,
+                    params.code,
+                    params.apparent_value,
+                    params.gas_limit,
+                    params.salt,
+                    backend,
+                    tracing,
+                    gas_scaling,
+                    params.fork,
+                    fork_schedule,
+                ))
+                .map(|(result, _)| serde_json::to_value(&result).unwrap_or_default()),
+            RecordedRequest::EstimateGas(params) => runtime
+                .block_on(estimate_gas_impl(
+                    params.address,
+                    params.caller,
+                    params.code,
+                    params.data,
+                    params.apparent_value,
+                    params.gas_limit,
+                    backend,
+                    gas_scaling,
+                    params.fork,
+                    fork_schedule,
+                    params.mode,
+                ))
+                .map(Value::from),
+        };
+        let recorded = serde_json::to_value(&run.result)?;
+        let differences = recording::diff(&recorded, &serde_json::to_value(&result)?);
+        if differences.is_empty() {
+            println!("run {}: same result", index);
+        } else {
+            mismatches += 1;
+            println!("run {}: different result", index);
+            for difference in differences {
+                println!("  {}", difference);
+            }
+        }
+    }
+    if mismatches > 0 {
+        return Err(format!("{} of {} runs differ", mismatches, runs.len()).into());
+    }
+    Ok(())
+}
+
+fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    match args.log4rs {
+        Some(log_config) if log_config != "" => {
+            log4rs::init_file(log_config, Default::default()).unwrap();
+        }
+        _ => {
+            let config_str = include_str!("../log4rs-local.yml");
+            let config = serde_yaml::from_str(config_str).unwrap();
             log4rs::init_raw_config(config).unwrap();
         }
     }
 
+    let gas_scaling = GasScaling::new(args.gas_scaling_factor, args.gas_rounding);
+    let fork_schedule =
+        ForkSchedule::new(args.fork).with_activations(args.fork_schedule.unwrap_or_default());
+
+    let backend_config = ScillaBackendConfig {
+        path: PathBuf::from(args.node_socket),
+        zil_scaling_factor: args.zil_scaling_factor,
+        precision_policy: args.precision_policy,
+        connections: NodePool::default(),
+    };
+    match args.command {
+        Some(Command::Exec(exec_args)) => {
+            return exec(
+                exec_args,
+                args.tracing,
+                args.zil_scaling_factor,
+                args.precision_policy,
+                gas_scaling,
+                fork_schedule,
+            )
+        }
+        Some(Command::Replay(replay_args)) => {
+            return replay(
+                replay_args,
+                args.tracing,
+                backend_config,
+                gas_scaling,
+                fork_schedule,
+            )
+        }
+        None => {}
+    }
+
     info!("Starting evm-ds");
 
+    let recorder = match &args.record {
+        Some(path) => Some(Arc::new(Recorder::open(path)?)),
+        None => None,
+    };
     let evm_sever = EvmServer {
         tracing: args.tracing,
-        backend_config: ScillaBackendConfig {
-            path: PathBuf::from(args.node_socket),
-            zil_scaling_factor: args.zil_scaling_factor,
-        },
-        gas_scaling_factor: args.gas_scaling_factor,
+        backend_config,
+        gas_scaling,
+        fork_schedule,
+        recorder,
     };
 
     // Setup a channel to signal a shutdown.
@@ -355,8 +1859,9 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     // Save the handle so that we can shut it down gracefully.
     *ipc_server_handle.lock().unwrap() = Some(ipc_server.close_handle());
 
-    // Build and start the HTTP server.
-    let builder = jsonrpc_http_server::ServerBuilder::new(io);
+    // Build and start the HTTP server, which also serves the metrics.
+    let builder =
+        jsonrpc_http_server::ServerBuilder::new(io).request_middleware(metrics::http_middleware);
     let http_server = builder
         .start_http(&SocketAddr::new(
             IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
@@ -384,3 +1889,970 @@ fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memorybackend::MemoryAccount;
+    use mocknode::{Behaviour, MockNode, MockState};
+    use std::time::Duration;
+
+    const ADDRESS: &str = "0x0000000000000000000000007265636569766572";
+    const CALLER: &str = "0x000000000000000000000000000073656e646572";
+
+    async fn run(backend: &InMemoryBackend, params: RunParams) -> (EvmResult, ExecutionDetails) {
+        run_evm_impl(
+            params,
+            backend.clone(),
+            false,
+            GasScaling::new(100, GasRounding::Ceil),
+            ForkSchedule::new(Fork::London),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn storage_persists_across_runs() {
+        let mut backend =
+            InMemoryBackend::new(MemoryState::default(), 1_000_000, PrecisionPolicy::Dust);
+        // PUSH1 42 PUSH1 0 SSTORE STOP
+        let store = RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            code: "602a60005500".to_string(),
+            apparent_value: "0".to_string(),
+            gas_limit: 1_000,
+            ..Default::default()
+        };
+        let (result, details) = run(&backend, store).await;
+        assert!(result.exit_reason.is_succeed());
+        // Two pushes, and a cold SSTORE of a zero slot.
+        assert_eq!((result.evm_gas_used, result.gas_used), (22_106, 222));
+        backend.apply(details.state_apply);
+
+        // PUSH1 0 SLOAD PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+        let load = RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            code: "60005460005260206000f3".to_string(),
+            apparent_value: "0".to_string(),
+            gas_limit: 1_000,
+            ..Default::default()
+        };
+        let (result, _) = run(&backend, load).await;
+        assert!(result.exit_reason.is_succeed());
+        assert_eq!(result.return_value, format!("{:064x}", 42));
+    }
+
+    #[tokio::test]
+    async fn transfer_in_zil_units() {
+        let caller = H160::from_str(CALLER).unwrap();
+        let mut state = MemoryState::default();
+        state.accounts.insert(
+            caller,
+            MemoryAccount {
+                balance: U256::from(10),
+                ..Default::default()
+            },
+        );
+        let mut backend = InMemoryBackend::new(state, 1_000_000, PrecisionPolicy::Dust);
+        let transfer = RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            apparent_value: "3".to_string(),
+            gas_limit: 1_000,
+            mode: Some("transaction".to_string()),
+            ..Default::default()
+        };
+        let (result, details) = run(&backend, transfer).await;
+        assert!(result.exit_reason.is_succeed());
+        backend.apply(details.state_apply);
+
+        let accounts = &backend.state().accounts;
+        let receiver = H160::from_str(ADDRESS).unwrap();
+        assert_eq!(accounts[&caller].balance, U256::from(7));
+        assert_eq!(accounts[&caller].nonce, U256::one());
+        assert_eq!(accounts[&receiver].balance, U256::from(3));
+    }
+
+    // The balance in Zil units and the dust of the `apply` entry modifying `address`.
+    fn balance(result: &EvmResult, address: H160) -> (U256, Option<U256>) {
+        result
+            .apply
+            .iter()
+            .find_map(|DirtyState(apply, dust)| match apply {
+                Apply::Modify {
+                    address: modified,
+                    basic,
+                    ..
+                } if *modified == address => Some((basic.balance, *dust)),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    // A transaction calling a contract with 10 Zil, which sends 1_500_001 wei to `receiver`.
+    async fn transfer_wei(
+        receiver: H160,
+        precision_policy: PrecisionPolicy,
+    ) -> (EvmResult, ExecutionDetails) {
+        // CALL(GAS, receiver, 1_500_001, 0, 0, 0, 0) STOP
+        let code = format!("60006000600060006216e36173{}5af100", hex::encode(receiver));
+        let mut state = MemoryState::default();
+        state.accounts.insert(
+            H160::from_str(ADDRESS).unwrap(),
+            MemoryAccount {
+                balance: U256::from(10),
+                code: hex::decode(code).unwrap(),
+                ..Default::default()
+            },
+        );
+        let backend = InMemoryBackend::new(state, 1_000_000, precision_policy);
+        let params = RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            apparent_value: "0".to_string(),
+            gas_limit: 1_000,
+            mode: Some("transaction".to_string()),
+            ..Default::default()
+        };
+        run(&backend, params).await
+    }
+
+    #[tokio::test]
+    async fn balances_are_conserved_by_a_transfer() {
+        let receiver = H160::repeat_byte(0xbb);
+        let (result, _) = transfer_wei(receiver, PrecisionPolicy::Dust).await;
+        assert!(result.exit_reason.is_succeed());
+        let sender = balance(&result, H160::from_str(ADDRESS).unwrap());
+        let receiver = balance(&result, receiver);
+        assert_eq!(sender, (U256::from(8), Some(U256::from(499_999))));
+        assert_eq!(receiver, (U256::from(1), Some(U256::from(500_001))));
+        let wei = |(zil, dust): (U256, Option<U256>)| zil * 1_000_000 + dust.unwrap_or_default();
+        assert_eq!(wei(sender) + wei(receiver), U256::from(10_000_000));
+    }
+
+    #[tokio::test]
+    async fn fractional_balances_can_be_rejected() {
+        let (result, details) =
+            transfer_wei(H160::repeat_byte(0xbb), PrecisionPolicy::Reject).await;
+        assert!(matches!(
+            result.exit_reason,
+            evm::ExitReason::Error(evm::ExitError::Other(_))
+        ));
+        assert!(result.apply.is_empty());
+        assert!(details.state_apply.is_empty());
+    }
+
+    #[tokio::test]
+    async fn access_list_saving_by_mode() {
+        // PUSH20 0xbb..bb BALANCE STOP
+        let code = format!("73{}3100", hex::encode(H160::repeat_byte(0xbb)));
+        let mut state = MemoryState::default();
+        state.accounts.insert(
+            H160::from_str(ADDRESS).unwrap(),
+            MemoryAccount {
+                code: hex::decode(&code).unwrap(),
+                ..Default::default()
+            },
+        );
+        let backend = InMemoryBackend::new(state, 1_000_000, PrecisionPolicy::Dust);
+        // Only the transaction pays the 2400 gas of the address in the list.
+        for (mode, saving) in [("raw", 2_500), ("transaction", 100)] {
+            let params = RunParams {
+                address: ADDRESS.to_string(),
+                caller: CALLER.to_string(),
+                code: code.clone(),
+                apparent_value: "0".to_string(),
+                gas_limit: 1_000,
+                mode: Some(mode.to_string()),
+                ..Default::default()
+            };
+            let (_, details) = run(&backend, params).await;
+            assert_eq!(details.access_list.len(), 1);
+            assert_eq!(details.access_list_gas_saving, saving);
+        }
+    }
+
+    #[tokio::test]
+    async fn access_list_keeps_the_slots_of_the_callee() {
+        let backend =
+            InMemoryBackend::new(MemoryState::default(), 1_000_000, PrecisionPolicy::Dust);
+        // PUSH1 42 PUSH1 0 SSTORE STOP
+        let params = RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            code: "602a60005500".to_string(),
+            apparent_value: "0".to_string(),
+            gas_limit: 1_000,
+            mode: Some("transaction".to_string()),
+            ..Default::default()
+        };
+        let (_, details) = run(&backend, params).await;
+        // The callee is warm, so only its slot saves anything: 2100 - 100 - 1900.
+        assert_eq!(
+            details.access_list,
+            vec![AccessListItem {
+                address: H160::from_str(ADDRESS).unwrap(),
+                storage_keys: vec![H256::zero()],
+            }]
+        );
+        assert_eq!(details.access_list_gas_saving, 100);
+    }
+
+    #[tokio::test]
+    async fn static_executions_change_nothing() {
+        let backend =
+            InMemoryBackend::new(MemoryState::default(), 1_000_000, PrecisionPolicy::Dust);
+        // PUSH1 42 PUSH1 0 SSTORE STOP
+        let store = RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            code: "602a60005500".to_string(),
+            apparent_value: "0".to_string(),
+            gas_limit: 1_000,
+            is_static: true,
+            ..Default::default()
+        };
+        let (result, details) = run(&backend, store).await;
+        assert!(!result.exit_reason.is_succeed());
+        assert!(result.apply.is_empty());
+        assert!(details.state_apply.is_empty());
+
+        // Without static, the transaction would increment the nonce of the caller.
+        let call = RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            apparent_value: "0".to_string(),
+            gas_limit: 1_000,
+            mode: Some("transaction".to_string()),
+            is_static: true,
+            ..Default::default()
+        };
+        let (result, details) = run(&backend, call.clone()).await;
+        assert!(result.exit_reason.is_succeed());
+        assert!(result.apply.is_empty());
+        assert!(details.state_apply.is_empty());
+
+        let transfer = RunParams {
+            apparent_value: "1".to_string(),
+            ..call
+        };
+        let error = run_evm_impl(
+            transfer,
+            backend,
+            false,
+            GasScaling::new(100, GasRounding::Ceil),
+            ForkSchedule::new(Fork::London),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+    }
+
+    #[tokio::test]
+    async fn overridden_executions_change_nothing() {
+        let backend =
+            InMemoryBackend::new(MemoryState::default(), 1_000_000, PrecisionPolicy::Dust);
+        // The caller has nothing to transfer, but in the overrides.
+        let overrides = [(
+            H160::from_str(CALLER).unwrap(),
+            overrides::AccountOverride {
+                balance: Some(U256::from(10)),
+                ..Default::default()
+            },
+        )];
+        let transfer = RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            apparent_value: "3".to_string(),
+            gas_limit: 1_000,
+            mode: Some("transaction".to_string()),
+            overrides: Some(overrides.into_iter().collect()),
+            ..Default::default()
+        };
+        let (result, details) = run(&backend, transfer.clone()).await;
+        assert!(result.exit_reason.is_succeed());
+        assert!(result.apply.is_empty());
+        assert!(details.state_apply.is_empty());
+
+        let transfer = RunParams {
+            overrides: None,
+            ..transfer
+        };
+        let (result, _) = run(&backend, transfer).await;
+        assert_eq!(
+            result.exit_reason,
+            evm::ExitReason::Error(evm::ExitError::OutOfFund)
+        );
+    }
+
+    #[tokio::test]
+    async fn executions_in_an_overridden_environment_change_nothing() {
+        let backend =
+            InMemoryBackend::new(MemoryState::default(), 1_000_000, PrecisionPolicy::Dust);
+        // PUSH1 42 PUSH1 0 SSTORE STOP
+        let store = RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            code: "602a60005500".to_string(),
+            apparent_value: "0".to_string(),
+            gas_limit: 1_000,
+            ..Default::default()
+        };
+        let (result, details) = run(&backend, store.clone()).await;
+        assert!(result.exit_reason.is_succeed());
+        assert!(!result.apply.is_empty());
+        assert!(!details.state_apply.is_empty());
+
+        let overridden = [
+            RunParams {
+                block_overrides: Some(BlockOverrides {
+                    number: Some(U256::from(10)),
+                    ..Default::default()
+                }),
+                ..store.clone()
+            },
+            RunParams {
+                block_overrides: Some(BlockOverrides {
+                    time: Some(U256::from(1_000)),
+                    ..Default::default()
+                }),
+                ..store.clone()
+            },
+            RunParams {
+                origin: Some(H160::repeat_byte(0xaa)),
+                ..store.clone()
+            },
+            RunParams {
+                gas_price: Some(U256::one()),
+                ..store.clone()
+            },
+        ];
+        for params in overridden {
+            let (result, details) = run(&backend, params).await;
+            assert!(result.exit_reason.is_succeed());
+            assert!(result.apply.is_empty());
+            assert!(details.state_apply.is_empty());
+        }
+
+        // Empty overrides change nothing of the environment.
+        let empty = RunParams {
+            block_overrides: Some(BlockOverrides::default()),
+            ..store
+        };
+        let (result, _) = run(&backend, empty).await;
+        assert!(!result.apply.is_empty());
+    }
+
+    #[tokio::test]
+    async fn one_node_connection_for_all_executions() {
+        let address = H160::from_str(ADDRESS).unwrap();
+        let node = MockNode::start(
+            MockState::default()
+                .storage(address, H256::from_low_u64_be(0), H256::from_low_u64_be(1))
+                .storage(address, H256::from_low_u64_be(1), H256::from_low_u64_be(2)),
+        );
+        let config = ScillaBackendConfig {
+            path: node.path().to_path_buf(),
+            zil_scaling_factor: 1_000_000,
+            precision_policy: PrecisionPolicy::Dust,
+            connections: NodePool::default(),
+        };
+        // PUSH1 0 SLOAD PUSH1 1 SLOAD ADD STOP
+        let params = RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            code: "6000546001540100".to_string(),
+            apparent_value: "0".to_string(),
+            gas_limit: 1_000,
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            let calls = node.calls();
+            let (result, _) = run_evm_impl(
+                params.clone(),
+                ScillaBackend::new(config.clone()),
+                false,
+                GasScaling::new(100, GasRounding::Ceil),
+                ForkSchedule::new(Fork::London),
+            )
+            .await
+            .unwrap();
+            assert!(result.exit_reason.is_succeed());
+            assert!(node.calls() - calls >= 2);
+            assert_eq!(node.connections(), 1);
+        }
+    }
+
+    fn server(node: &MockNode) -> EvmServer {
+        EvmServer {
+            tracing: false,
+            backend_config: ScillaBackendConfig {
+                path: node.path().to_path_buf(),
+                zil_scaling_factor: 1_000_000,
+                precision_policy: PrecisionPolicy::Dust,
+                connections: NodePool::default(),
+            },
+            gas_scaling: GasScaling::new(100, GasRounding::Ceil),
+            fork_schedule: ForkSchedule::new(Fork::London),
+            recorder: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn call_reports_node_errors() {
+        let node = MockNode::start(MockState::default());
+        let server = server(&node);
+        // PUSH1 0 SLOAD STOP
+        let call = || {
+            server.call(
+                ADDRESS.to_string(),
+                CALLER.to_string(),
+                "60005400".to_string(),
+                String::new(),
+                "0".to_string(),
+                1_000,
+                None,
+                None,
+                None,
+            )
+        };
+        assert!(call().await.unwrap().exit_reason.is_succeed());
+
+        let failures = [
+            (
+                Behaviour::Fixed(Value::from(vec![Value::Bool(true), Value::from(42)])),
+                "malformed_state",
+            ),
+            (Behaviour::Delayed(Duration::from_secs(3)), "timeout"),
+        ];
+        for (behaviour, code) in failures {
+            node.set_behaviour(behaviour);
+            let error = call().await.err().unwrap();
+            assert_eq!(error.code, ErrorCode::ServerError(-32001));
+            assert_eq!(error.data.unwrap()["code"], code);
+        }
+    }
+
+    #[tokio::test]
+    async fn run_v2_rejects_unknown_fields() {
+        let node = MockNode::start(MockState::default());
+        let server = server(&node);
+        let params = |extra: Option<&str>| {
+            let mut params = serde_json::Map::new();
+            params.insert("address".to_string(), Value::from(ADDRESS));
+            params.insert("caller".to_string(), Value::from(CALLER));
+            // PUSH1 0 SLOAD STOP
+            params.insert("code".to_string(), Value::from("60005400"));
+            params.insert("data".to_string(), Value::from(""));
+            params.insert("apparent_value".to_string(), Value::from("0"));
+            params.insert("gas_limit".to_string(), Value::from(1_000));
+            if let Some(extra) = extra {
+                params.insert(extra.to_string(), Value::from(1_000));
+            }
+            Params::Map(params)
+        };
+        let result = server.run_v2(params(None)).await.unwrap();
+        assert!(result.exit_reason.is_succeed());
+
+        // A misspelt field must not be silently ignored.
+        let error = server.run_v2(params(Some("gasLimit"))).await.unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidParams);
+        assert!(
+            error.message.contains("unknown field `gasLimit`"),
+            "{}",
+            error.message
+        );
+    }
+
+    #[tokio::test]
+    async fn block_number_override_selects_the_fork() {
+        let backend =
+            InMemoryBackend::new(MemoryState::default(), 1_000_000, PrecisionPolicy::Dust);
+        let fork_schedule = ForkSchedule::new(Fork::Istanbul)
+            .with_activations([(10, Fork::Berlin)].into_iter().collect());
+        // PUSH1 0 SLOAD STOP: an SLOAD costs 800 in Istanbul, and 2100 when cold in Berlin.
+        let sload_at = |number: Option<u64>| RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            code: "60005400".to_string(),
+            apparent_value: "0".to_string(),
+            gas_limit: 1_000,
+            block_overrides: number.map(|number| BlockOverrides {
+                number: Some(U256::from(number)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        for (number, evm_gas_used) in [(None, 803), (Some(9), 803), (Some(10), 2_103)] {
+            let (result, _) = run_evm_impl(
+                sload_at(number),
+                backend.clone(),
+                false,
+                GasScaling::new(100, GasRounding::Ceil),
+                fork_schedule.clone(),
+            )
+            .await
+            .unwrap();
+            assert!(result.exit_reason.is_succeed());
+            assert_eq!(result.evm_gas_used, evm_gas_used, "block {:?}", number);
+        }
+    }
+
+    // The counters are shared with the tests running alongside, which only add to them.
+    #[tokio::test]
+    async fn caught_panics_are_counted() {
+        let node = MockNode::start(MockState::default());
+        node.set_behaviour(Behaviour::Delayed(Duration::from_secs(3)));
+        // PUSH1 0 SLOAD STOP
+        let estimate_gas = |server: &EvmServer| {
+            server.estimate_gas(
+                ADDRESS.to_string(),
+                CALLER.to_string(),
+                "60005400".to_string(),
+                String::new(),
+                "0".to_string(),
+                1_000,
+                None,
+                None,
+            )
+        };
+        // The block gas limit is asked first.
+        let (panics, timeouts) = (
+            metrics::caught_panics("timeout"),
+            metrics::node_query_timeouts("BLOCKGASLIMIT"),
+        );
+        let error = estimate_gas(&server(&node)).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "timeout");
+        assert!(metrics::caught_panics("timeout") > panics);
+        assert!(metrics::node_query_timeouts("BLOCKGASLIMIT") > timeouts);
+
+        // The fork of the execution depends on the block number, asked first.
+        let scheduled = EvmServer {
+            fork_schedule: ForkSchedule::new(Fork::Berlin)
+                .with_activations([(10, Fork::London)].into_iter().collect()),
+            ..server(&node)
+        };
+        let (panics, timeouts) = (
+            metrics::caught_panics("timeout"),
+            metrics::node_query_timeouts("BLOCKNUMBER"),
+        );
+        let error = scheduled
+            .run(
+                ADDRESS.to_string(),
+                CALLER.to_string(),
+                "60005400".to_string(),
+                String::new(),
+                "0".to_string(),
+                1_000,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "timeout");
+        assert!(metrics::caught_panics("timeout") > panics);
+        assert!(metrics::node_query_timeouts("BLOCKNUMBER") > timeouts);
+
+        // Queries that cannot even connect are counted as well.
+        let server = server(&node);
+        drop(node);
+        let (panics, queries) = (
+            metrics::caught_panics("node_unreachable"),
+            metrics::node_queries("BLOCKGASLIMIT"),
+        );
+        let error = estimate_gas(&server).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "node_unreachable");
+        assert!(metrics::caught_panics("node_unreachable") > panics);
+        assert!(metrics::node_queries("BLOCKGASLIMIT") > queries);
+    }
+
+    // `replay` runs its own runtime, so this test cannot run in one.
+    #[test]
+    fn recorded_executions_replay_the_same() {
+        let address = H160::from_str(ADDRESS).unwrap();
+        let node = MockNode::start(MockState::default().storage(
+            address,
+            H256::from_low_u64_be(0),
+            H256::from_low_u64_be(1),
+        ));
+        let recording =
+            std::env::temp_dir().join(format!("evm-ds-recording-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&recording);
+        let server = EvmServer {
+            recorder: Some(Arc::new(Recorder::open(&recording).unwrap())),
+            ..server(&node)
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        // PUSH1 0 SLOAD PUSH1 1 ADD PUSH1 0 SSTORE STOP
+        let code = "60005460010160005500";
+        let result = runtime
+            .block_on(server.run(
+                ADDRESS.to_string(),
+                CALLER.to_string(),
+                code.to_string(),
+                String::new(),
+                "0".to_string(),
+                1_000,
+                None,
+                None,
+                None,
+            ))
+            .unwrap();
+        assert!(result.exit_reason.is_succeed());
+        runtime
+            .block_on(server.estimate_gas(
+                ADDRESS.to_string(),
+                CALLER.to_string(),
+                code.to_string(),
+                String::new(),
+                "0".to_string(),
+                1_000,
+                None,
+                None,
+            ))
+            .unwrap();
+        let result = runtime
+            .block_on(server.create(
+                CALLER.to_string(),
+                "602a60005360016000f3".to_string(),
+                "0".to_string(),
+                1_000,
+                None,
+            ))
+            .unwrap();
+        assert!(result.exit_reason.is_succeed());
+        drop(runtime);
+        drop(node);
+
+        let recorded: Vec<RecordedRun> = recording::read(&recording).unwrap();
+        assert_eq!(recorded.len(), 3);
+        assert!(recorded.iter().all(|run| !run.queries.is_empty()));
+        let replayed = replay(
+            ReplayArgs {
+                recording: recording.clone(),
+            },
+            false,
+            server.backend_config.clone(),
+            server.gas_scaling,
+            server.fork_schedule.clone(),
+        );
+        std::fs::remove_file(&recording).unwrap();
+        replayed.unwrap();
+    }
+
+    // `exec` runs its own runtime, so this test cannot run in one.
+    #[test]
+    fn exec_dumps_the_state_it_loaded_with_its_changes() {
+        let address = H160::from_str(ADDRESS).unwrap();
+        let mut state = MemoryState::default();
+        state.env.block_number = U256::from(7);
+        state.env.chain_id = U256::from(33_101);
+        state.accounts.insert(
+            address,
+            MemoryAccount {
+                balance: U256::from(5),
+                nonce: U256::one(),
+                storage: [(H256::from_low_u64_be(1), H256::from_low_u64_be(2))]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            },
+        );
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("evm-ds-exec-state-{}.json", std::process::id()));
+        let output = dir.join(format!("evm-ds-exec-dump-{}.json", std::process::id()));
+        state.dump(&input).unwrap();
+
+        let exec_code = |code: &str| {
+            exec(
+                ExecArgs {
+                    code: code.to_string(),
+                    data: String::new(),
+                    state: Some(input.clone()),
+                    address: ADDRESS.to_string(),
+                    caller: CALLER.to_string(),
+                    value: "0".to_string(),
+                    gas_limit: 1_000,
+                    mode: None,
+                    tracer: None,
+                    dump: Some(output.clone()),
+                },
+                false,
+                1_000_000,
+                PrecisionPolicy::Dust,
+                GasScaling::new(100, GasRounding::Ceil),
+                ForkSchedule::new(Fork::London),
+            )
+            .unwrap();
+            MemoryState::load(&output).unwrap()
+        };
+        // PUSH1 42 PUSH1 0 SSTORE STOP
+        let stored = exec_code("602a60005500");
+        // PUSH1 42 PUSH1 0 SSTORE PUSH1 0 DUP1 REVERT
+        let reverted = exec_code("602a600055600080fd");
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+
+        let mut expected = state.accounts[&address].clone();
+        expected
+            .storage
+            .insert(H256::zero(), H256::from_low_u64_be(42));
+        assert_eq!(stored.env, state.env);
+        assert_eq!(stored.accounts[&address], expected);
+        // The changes of a failed execution are not dumped.
+        assert_eq!(reverted, state);
+    }
+
+    #[tokio::test]
+    async fn aborted_executions_report_what_they_did() {
+        let node = MockNode::start(MockState::default());
+        // Storage values that are not strings are malformed.
+        node.set_behaviour(Behaviour::Fixed(Value::from(vec![
+            Value::Bool(true),
+            Value::from(42),
+        ])));
+        // PUSH1 0 PUSH1 0 LOG0 PUSH1 0 SLOAD STOP
+        let params = RunParams {
+            address: ADDRESS.to_string(),
+            caller: CALLER.to_string(),
+            code: "60006000a060005400".to_string(),
+            apparent_value: "0".to_string(),
+            gas_limit: 1_000,
+            ..Default::default()
+        };
+        let (result, _) = run_evm_impl(
+            params,
+            ScillaBackend::new(server(&node).backend_config),
+            false,
+            GasScaling::new(100, GasRounding::Ceil),
+            ForkSchedule::new(Fork::London),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(result.error, Some(EvmError::MalformedState(_))));
+        assert!(result.logs.is_empty());
+        let partial = result.partial.unwrap();
+        assert_eq!(partial.logs.len(), 1);
+        assert!(partial.gas_used > 0);
+        assert_eq!(partial.gas_used, result.gas_used);
+    }
+
+    async fn create(
+        backend: &InMemoryBackend,
+        caller: &str,
+        code: &str,
+        salt: Option<&str>,
+    ) -> (EvmResult, Vec<Apply<BTreeMap<H256, H256>>>) {
+        create_impl(
+            caller.to_string(),
+            code.to_string(),
+            "0".to_string(),
+            1_000,
+            salt.map(str::to_string),
+            backend.clone(),
+            false,
+            GasScaling::new(100, GasRounding::Ceil),
+            None,
+            ForkSchedule::new(Fork::London),
+        )
+        .await
+        .unwrap()
+    }
+
+    // The `apply` entry modifying `address`.
+    fn modified(result: &EvmResult, address: H160) -> (Basic, Option<Vec<u8>>) {
+        result
+            .apply
+            .iter()
+            .find_map(|DirtyState(apply, _)| match apply {
+                Apply::Modify {
+                    address: modified,
+                    basic,
+                    code,
+                    ..
+                } if *modified == address => Some((basic.clone(), code.clone())),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn create_derives_the_address_from_the_caller_nonce() {
+        let mut backend =
+            InMemoryBackend::new(MemoryState::default(), 1_000_000, PrecisionPolicy::Dust);
+        let caller = "0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0";
+        // PUSH1 0x2a PUSH1 0 MSTORE8 PUSH1 1 PUSH1 0 RETURN, deploying the code 0x2a.
+        let init_code = "602a60005360016000f3";
+        let (result, state_apply) = create(&backend, caller, init_code, None).await;
+        assert!(result.exit_reason.is_succeed());
+        let contract = H160::from_str("0xcd234a471b72ba2f1ccf0a70fcaba648a5eecd8d").unwrap();
+        assert_eq!(result.contract_address, Some(contract));
+        assert_eq!(modified(&result, contract).1, Some(vec![0x2a]));
+        let caller_address = H160::from_str(caller).unwrap();
+        assert_eq!(modified(&result, caller_address).0.nonce, U256::one());
+        backend.apply(state_apply);
+
+        // The next contract of the caller is at its next nonce.
+        let (result, _) = create(&backend, caller, init_code, None).await;
+        assert_eq!(
+            result.contract_address,
+            Some(H160::from_str("0x343c43a37d37dff08ae8c4a11544c718abb4fcf8").unwrap())
+        );
+        assert_eq!(modified(&result, caller_address).0.nonce, U256::from(2));
+    }
+
+    #[tokio::test]
+    async fn create2_derives_the_address_from_the_salt() {
+        let backend =
+            InMemoryBackend::new(MemoryState::default(), 1_000_000, PrecisionPolicy::Dust);
+        // The first example of EIP-1014.
+        let (result, _) = create(
+            &backend,
+            "0x0000000000000000000000000000000000000000",
+            "00",
+            Some("0x0000000000000000000000000000000000000000000000000000000000000000"),
+        )
+        .await;
+        assert!(result.exit_reason.is_succeed());
+        assert_eq!(
+            result.contract_address,
+            Some(H160::from_str("0x4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38").unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn create_enforces_the_code_size_limit() {
+        let backend =
+            InMemoryBackend::new(MemoryState::default(), 1_000_000, PrecisionPolicy::Dust);
+        // PUSH2 0x6001 PUSH1 0 RETURN, deploying 24577 zero bytes, one more than EIP-170 allows.
+        let (result, _) = create(&backend, CALLER, "6160016000f3", None).await;
+        assert_eq!(
+            result.exit_reason,
+            evm::ExitReason::Error(evm::ExitError::CreateContractLimit)
+        );
+        assert_eq!(result.contract_address, None);
+    }
+
+    async fn estimate_gas(
+        backend: &InMemoryBackend,
+        code: &str,
+        gas_limit: u64,
+        gas_scaling: GasScaling,
+    ) -> Result<u64> {
+        estimate_gas_impl(
+            ADDRESS.to_string(),
+            CALLER.to_string(),
+            code.to_string(),
+            String::new(),
+            "0".to_string(),
+            gas_limit,
+            backend.clone(),
+            gas_scaling,
+            None,
+            ForkSchedule::new(Fork::London),
+            None,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn estimate_gas_is_the_smallest_limit_that_succeeds() {
+        let backend = InMemoryBackend::new(MemoryState::default(), 1, PrecisionPolicy::Dust);
+        // PUSH1 42 PUSH1 0 SSTORE STOP
+        let code = "602a60005500";
+        let unscaled = GasScaling::new(1, GasRounding::Ceil);
+        let estimate = estimate_gas(&backend, code, 100_000, unscaled)
+            .await
+            .unwrap();
+        assert_eq!(estimate, 22_106);
+        for (gas_limit, succeeds) in [(estimate, true), (estimate - 1, false)] {
+            let params = RunParams {
+                address: ADDRESS.to_string(),
+                caller: CALLER.to_string(),
+                code: code.to_string(),
+                apparent_value: "0".to_string(),
+                gas_limit,
+                ..Default::default()
+            };
+            let (result, _) = run_evm_impl(
+                params,
+                backend.clone(),
+                false,
+                unscaled,
+                ForkSchedule::new(Fork::London),
+            )
+            .await
+            .unwrap();
+            assert_eq!(result.exit_reason.is_succeed(), succeeds);
+        }
+
+        // In Scilla gas, rounded up: 221 Scilla gas is only 22100 EVM gas.
+        let scaled = GasScaling::new(100, GasRounding::Ceil);
+        assert_eq!(
+            estimate_gas(&backend, code, 1_000, scaled).await.unwrap(),
+            222
+        );
+    }
+
+    // The counters are shared with the tests running alongside, which only add to them.
+    #[tokio::test]
+    async fn estimate_gas_executions_are_counted() {
+        let backend = InMemoryBackend::new(MemoryState::default(), 1, PrecisionPolicy::Dust);
+        let succeeded = evm::ExitReason::Succeed(evm::ExitSucceed::Stopped);
+        let (executions, estimates) = (
+            metrics::executions("estimate_gas", &succeeded),
+            metrics::gas_estimates(),
+        );
+        // PUSH1 42 PUSH1 0 SSTORE STOP: one execution with the whole limit, then the search.
+        estimate_gas(
+            &backend,
+            "602a60005500",
+            100_000,
+            GasScaling::new(1, GasRounding::Ceil),
+        )
+        .await
+        .unwrap();
+        assert!(metrics::executions("estimate_gas", &succeeded) >= executions + 2);
+        assert!(metrics::gas_estimates() > estimates);
+    }
+
+    #[tokio::test]
+    async fn estimate_gas_is_capped_by_the_block_gas_limit() {
+        let mut state = MemoryState::default();
+        // One less than the SSTORE below needs.
+        state.env.block_gas_limit = U256::from(22_105);
+        let backend = InMemoryBackend::new(state, 1, PrecisionPolicy::Dust);
+        // PUSH1 42 PUSH1 0 SSTORE STOP
+        let unscaled = GasScaling::new(1, GasRounding::Ceil);
+        let error = estimate_gas(&backend, "602a60005500", 100_000, unscaled)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::ServerError(-32000));
+        assert!(error.message.contains("(22105)"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn estimate_gas_reports_the_revert_reason() {
+        let backend = InMemoryBackend::new(MemoryState::default(), 1, PrecisionPolicy::Dust);
+        // Reverts with Error("nope"): the selector, the offset, the length and the string
+        // are stored one after the other, then the 100 bytes are returned.
+        let code = concat!(
+            "6308c379a060e01b600052",
+            "6020600452",
+            "6004602452",
+            "636e6f706560e01b604452",
+            "60646000fd",
+        );
+        let scaling = GasScaling::new(100, GasRounding::Ceil);
+        let error = estimate_gas(&backend, code, 1_000, scaling)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::ServerError(3));
+        assert_eq!(error.message, "execution reverted: nope");
+    }
+}
@@ -0,0 +1,294 @@
+/// Backend keeping all state in memory, to execute without a node. The state is loaded from
+/// and dumped to JSON files.
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use evm::backend::{Apply, Backend, Basic};
+use jsonrpc_core::serde_json;
+use primitive_types::{H160, H256, U256};
+use serde::{Deserialize, Serializer};
+
+use crate::scillabackend::{PrecisionPolicy, ZilliqaBackend};
+
+/// An account, as stored by the node.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryAccount {
+    /// In Zil units.
+    pub balance: U256,
+    pub nonce: U256,
+    /// EVM code, in hex in JSON.
+    #[serde(deserialize_with = "from_hex", serialize_with = "to_hex")]
+    pub code: Vec<u8>,
+    pub storage: BTreeMap<H256, H256>,
+}
+
+/// The transaction and block environment, as seen by the EVM.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryEnv {
+    pub gas_price: U256,
+    pub origin: H160,
+    pub chain_id: U256,
+    pub block_number: U256,
+    pub block_coinbase: H160,
+    pub block_timestamp: U256,
+    pub block_difficulty: U256,
+    pub block_gas_limit: U256,
+    pub block_base_fee_per_gas: U256,
+    pub block_hashes: BTreeMap<U256, H256>,
+}
+
+/// The whole state of an `InMemoryBackend`, in the format of its JSON files.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryState {
+    pub env: MemoryEnv,
+    pub accounts: BTreeMap<H160, MemoryAccount>,
+}
+
+impl MemoryState {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn dump(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+pub(crate) fn from_hex<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<u8>, D::Error> {
+    let code = String::deserialize(deserializer)?;
+    hex::decode(code.trim_start_matches("0x")).map_err(serde::de::Error::custom)
+}
+
+fn to_hex<S: Serializer>(code: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(code))
+}
+
+#[derive(Clone)]
+pub struct InMemoryBackend {
+    state: MemoryState,
+    // Scaling factor of Eth <-> Zil, as in `ScillaBackendConfig`.
+    zil_scaling_factor: u64,
+    precision_policy: PrecisionPolicy,
+}
+
+impl InMemoryBackend {
+    pub fn new(
+        state: MemoryState,
+        zil_scaling_factor: u64,
+        precision_policy: PrecisionPolicy,
+    ) -> Self {
+        Self {
+            state,
+            zil_scaling_factor,
+            precision_policy,
+        }
+    }
+
+    pub fn state(&self) -> &MemoryState {
+        &self.state
+    }
+
+    /// Applies the state changes of an execution, with balances in Eth units, so that the
+    /// next execution sees them. Balances are truncated to whole Zils, as the node keeps them.
+    pub fn apply(&mut self, changes: impl IntoIterator<Item = Apply<BTreeMap<H256, H256>>>) {
+        for change in changes {
+            match change {
+                Apply::Modify {
+                    address,
+                    basic,
+                    code,
+                    storage,
+                    reset_storage,
+                } => {
+                    let (balance, _) = self.split_eth_to_zil(basic.balance);
+                    let account = self.state.accounts.entry(address).or_default();
+                    account.balance = balance;
+                    account.nonce = basic.nonce;
+                    if let Some(code) = code {
+                        account.code = code;
+                    }
+                    if reset_storage {
+                        account.storage.clear();
+                    }
+                    for (index, value) in storage {
+                        if value.is_zero() {
+                            account.storage.remove(&index);
+                        } else {
+                            account.storage.insert(index, value);
+                        }
+                    }
+                }
+                Apply::Delete { address } => {
+                    self.state.accounts.remove(&address);
+                }
+            }
+        }
+    }
+}
+
+impl ZilliqaBackend for InMemoryBackend {
+    fn zil_scaling_factor(&self) -> u64 {
+        self.zil_scaling_factor
+    }
+
+    fn precision_policy(&self) -> PrecisionPolicy {
+        self.precision_policy
+    }
+}
+
+impl Backend for InMemoryBackend {
+    fn gas_price(&self) -> U256 {
+        self.state.env.gas_price
+    }
+
+    fn origin(&self) -> H160 {
+        self.state.env.origin
+    }
+
+    fn block_hash(&self, number: U256) -> H256 {
+        self.state
+            .env
+            .block_hashes
+            .get(&number)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn block_number(&self) -> U256 {
+        self.state.env.block_number
+    }
+
+    fn block_coinbase(&self) -> H160 {
+        self.state.env.block_coinbase
+    }
+
+    fn block_timestamp(&self) -> U256 {
+        self.state.env.block_timestamp
+    }
+
+    fn block_difficulty(&self) -> U256 {
+        self.state.env.block_difficulty
+    }
+
+    fn block_gas_limit(&self) -> U256 {
+        self.state.env.block_gas_limit
+    }
+
+    fn block_base_fee_per_gas(&self) -> U256 {
+        self.state.env.block_base_fee_per_gas
+    }
+
+    fn chain_id(&self) -> U256 {
+        self.state.env.chain_id
+    }
+
+    fn exists(&self, address: H160) -> bool {
+        self.state.accounts.contains_key(&address)
+    }
+
+    fn basic(&self, address: H160) -> Basic {
+        match self.state.accounts.get(&address) {
+            Some(account) => Basic {
+                balance: self.scale_zil_to_eth(account.balance),
+                nonce: account.nonce,
+            },
+            None => Basic::default(),
+        }
+    }
+
+    fn code(&self, address: H160) -> Vec<u8> {
+        self.state
+            .accounts
+            .get(&address)
+            .map(|account| account.code.clone())
+            .unwrap_or_default()
+    }
+
+    fn storage(&self, address: H160, index: H256) -> H256 {
+        self.state
+            .accounts
+            .get(&address)
+            .and_then(|account| account.storage.get(&index).copied())
+            .unwrap_or_default()
+    }
+
+    fn original_storage(&self, address: H160, index: H256) -> Option<H256> {
+        Some(self.storage(address, index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_round_trips_through_json() {
+        let json = r#"{
+            "env": { "block_number": "0x10", "block_hashes": { "0xf": "0x1111111111111111111111111111111111111111111111111111111111111111" } },
+            "accounts": {
+                "0x00000000000000000000000000000000000000aa": {
+                    "balance": "0x3e8",
+                    "code": "0x6000",
+                    "storage": { "0x0000000000000000000000000000000000000000000000000000000000000001": "0x000000000000000000000000000000000000000000000000000000000000002a" }
+                }
+            }
+        }"#;
+        let state: MemoryState = serde_json::from_str(json).unwrap();
+        let backend = InMemoryBackend::new(state.clone(), 1_000_000, PrecisionPolicy::Dust);
+        let address = H160::from_low_u64_be(0xaa);
+        assert_eq!(backend.basic(address).balance, U256::from(1_000_000_000));
+        assert_eq!(backend.code(address), vec![0x60, 0x00]);
+        assert_eq!(
+            backend.storage(address, H256::from_low_u64_be(1)),
+            H256::from_low_u64_be(42)
+        );
+        assert_eq!(backend.block_hash(U256::from(15)), H256::repeat_byte(0x11));
+        assert!(!backend.exists(H160::zero()));
+
+        let dumped = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<MemoryState>(&dumped).unwrap(), state);
+    }
+
+    #[test]
+    fn apply_changes() {
+        let mut backend = InMemoryBackend::new(MemoryState::default(), 10, PrecisionPolicy::Dust);
+        let (address, deleted) = (H160::repeat_byte(1), H160::repeat_byte(2));
+        backend
+            .state
+            .accounts
+            .insert(deleted, MemoryAccount::default());
+        let storage = [
+            (H256::from_low_u64_be(1), H256::from_low_u64_be(7)),
+            (H256::from_low_u64_be(2), H256::zero()),
+        ];
+        backend.apply(vec![
+            Apply::Modify {
+                address,
+                basic: Basic {
+                    balance: U256::from(1234),
+                    nonce: U256::one(),
+                },
+                code: Some(vec![0x00]),
+                storage: storage.into_iter().collect(),
+                reset_storage: false,
+            },
+            Apply::Delete { address: deleted },
+        ]);
+        let account = &backend.state().accounts[&address];
+        assert_eq!(
+            (account.balance, account.nonce),
+            (U256::from(123), U256::one())
+        );
+        assert_eq!(account.code, vec![0x00]);
+        assert_eq!(account.storage.len(), 1);
+        assert!(!backend.exists(deleted));
+    }
+}
@@ -0,0 +1,200 @@
+/// Prometheus metrics of the server, served at `/metrics` on the HTTP port.
+use std::time::Duration;
+
+use evm::{ExitError, ExitFatal, ExitReason};
+use jsonrpc_http_server::hyper;
+use jsonrpc_http_server::RequestMiddlewareAction;
+use lazy_static::lazy_static;
+use log::error;
+use prometheus::{
+    exponential_buckets, register_histogram, register_histogram_vec, register_int_counter_vec,
+    Encoder, Histogram, HistogramVec, IntCounterVec, TextEncoder,
+};
+
+use crate::error::EvmError;
+
+lazy_static! {
+    static ref EXECUTIONS: IntCounterVec = register_int_counter_vec!(
+        "evm_ds_executions_total",
+        "Executions, by kind (run, create or estimate_gas) and exit reason.",
+        &["kind", "exit_reason"]
+    )
+    .unwrap();
+    static ref EXECUTION_SECONDS: HistogramVec = register_histogram_vec!(
+        "evm_ds_execution_duration_seconds",
+        "Duration of executions, including the queries to the node, by kind.",
+        &["kind"]
+    )
+    .unwrap();
+    static ref EXECUTION_GAS_USED: HistogramVec = register_histogram_vec!(
+        "evm_ds_execution_gas_used",
+        "EVM gas used by executions, by kind.",
+        &["kind"],
+        exponential_buckets(1_000.0, 4.0, 10).unwrap()
+    )
+    .unwrap();
+    static ref GAS_ESTIMATE_SECONDS: Histogram = register_histogram!(
+        "evm_ds_gas_estimate_duration_seconds",
+        "Duration of gas estimations, including all the executions of their search."
+    )
+    .unwrap();
+    static ref NODE_QUERIES: IntCounterVec = register_int_counter_vec!(
+        "evm_ds_node_queries_total",
+        "Queries to the node over IPC, by query name, including those that timed out.",
+        &["query"]
+    )
+    .unwrap();
+    static ref NODE_QUERY_SECONDS: HistogramVec = register_histogram_vec!(
+        "evm_ds_node_query_duration_seconds",
+        "Duration of the queries to the node, by query name.",
+        &["query"],
+        exponential_buckets(0.000_1, 4.0, 10).unwrap()
+    )
+    .unwrap();
+    static ref NODE_QUERY_TIMEOUTS: IntCounterVec = register_int_counter_vec!(
+        "evm_ds_node_query_timeouts_total",
+        "Queries to the node that timed out, by query name.",
+        &["query"]
+    )
+    .unwrap();
+    static ref CAUGHT_PANICS: IntCounterVec = register_int_counter_vec!(
+        "evm_ds_caught_panics_total",
+        "Panics caught during executions, by error code.",
+        &["code"]
+    )
+    .unwrap();
+    static ref PRECOMPILE_CALLS: IntCounterVec = register_int_counter_vec!(
+        "evm_ds_precompile_calls_total",
+        "Calls to precompiled contracts, by precompile.",
+        &["precompile"]
+    )
+    .unwrap();
+}
+
+pub fn observe_execution(
+    kind: &str,
+    exit_reason: &ExitReason,
+    duration: Duration,
+    evm_gas_used: u64,
+) {
+    EXECUTIONS
+        .with_label_values(&[kind, &exit_reason_label(exit_reason)])
+        .inc();
+    EXECUTION_SECONDS
+        .with_label_values(&[kind])
+        .observe(duration.as_secs_f64());
+    EXECUTION_GAS_USED
+        .with_label_values(&[kind])
+        .observe(evm_gas_used as f64);
+}
+
+pub fn observe_gas_estimate(duration: Duration) {
+    GAS_ESTIMATE_SECONDS.observe(duration.as_secs_f64());
+}
+
+pub fn observe_node_query(query: &str, duration: Duration, timed_out: bool) {
+    NODE_QUERIES.with_label_values(&[query]).inc();
+    NODE_QUERY_SECONDS
+        .with_label_values(&[query])
+        .observe(duration.as_secs_f64());
+    if timed_out {
+        NODE_QUERY_TIMEOUTS.with_label_values(&[query]).inc();
+    }
+}
+
+pub fn count_caught_panic(error: &EvmError) {
+    CAUGHT_PANICS.with_label_values(&[error.code()]).inc();
+}
+
+pub fn count_precompile_call(precompile: &str) {
+    PRECOMPILE_CALLS.with_label_values(&[precompile]).inc();
+}
+
+#[cfg(test)]
+pub fn executions(kind: &str, exit_reason: &ExitReason) -> u64 {
+    EXECUTIONS
+        .with_label_values(&[kind, &exit_reason_label(exit_reason)])
+        .get()
+}
+
+#[cfg(test)]
+pub fn gas_estimates() -> u64 {
+    GAS_ESTIMATE_SECONDS.get_sample_count()
+}
+
+#[cfg(test)]
+pub fn caught_panics(code: &str) -> u64 {
+    CAUGHT_PANICS.with_label_values(&[code]).get()
+}
+
+#[cfg(test)]
+pub fn node_queries(query: &str) -> u64 {
+    NODE_QUERIES.with_label_values(&[query]).get()
+}
+
+#[cfg(test)]
+pub fn node_query_timeouts(query: &str) -> u64 {
+    NODE_QUERY_TIMEOUTS.with_label_values(&[query]).get()
+}
+
+// The exit reason without its free-form messages, which would make a label per message.
+fn exit_reason_label(exit_reason: &ExitReason) -> String {
+    match exit_reason {
+        ExitReason::Error(ExitError::Other(_)) => "Error(Other)".to_string(),
+        ExitReason::Fatal(ExitFatal::Other(_)) => "Fatal(Other)".to_string(),
+        ExitReason::Fatal(ExitFatal::CallErrorAsFatal(ExitError::Other(_))) => {
+            "Fatal(CallErrorAsFatal(Other))".to_string()
+        }
+        _ => format!("{:?}", exit_reason),
+    }
+}
+
+/// Answers `GET /metrics` on the HTTP server, and passes the other requests on to JSON-RPC.
+pub fn http_middleware(request: hyper::Request<hyper::Body>) -> RequestMiddlewareAction {
+    if request.method() != hyper::Method::GET || request.uri().path() != "/metrics" {
+        return RequestMiddlewareAction::Proceed {
+            should_continue_on_invalid_cors: false,
+            request,
+        };
+    }
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+    let response = match encoder.encode(&prometheus::gather(), &mut buffer) {
+        Ok(()) => hyper::Response::builder()
+            .header(hyper::header::CONTENT_TYPE, encoder.format_type())
+            .body(hyper::Body::from(buffer)),
+        Err(e) => {
+            error!("Cannot encode metrics: {}", e);
+            hyper::Response::builder()
+                .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
+                .body(hyper::Body::empty())
+        }
+    };
+    RequestMiddlewareAction::Respond {
+        should_validate_hosts: false,
+        response: Box::pin(futures::future::ok(response.unwrap())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_reason_labels_have_no_messages() {
+        assert_eq!(
+            exit_reason_label(&ExitReason::Succeed(evm::ExitSucceed::Returned)),
+            "Succeed(Returned)"
+        );
+        assert_eq!(
+            exit_reason_label(&ExitReason::Error(ExitError::OutOfGas)),
+            "Error(OutOfGas)"
+        );
+        assert_eq!(
+            exit_reason_label(&ExitReason::Error(ExitError::Other(
+                "balance of 0x12".into()
+            ))),
+            "Error(Other)"
+        );
+    }
+}
@@ -0,0 +1,227 @@
+/// A mock of the node, answering the IPC queries of `ScillaBackend` from a given state over
+/// a Unix socket, so that the backend can be tested end to end without a node.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use jsonrpc_core::{serde_json, Error, IoHandler, Params, Result, Value};
+use primitive_types::{H160, H256, U256};
+use protobuf::Message;
+
+use crate::protos::ScillaMessage;
+
+/// How the node answers queries.
+#[derive(Clone, Debug)]
+pub enum Behaviour {
+    /// From its state.
+    Normal,
+    /// From its state, after a delay.
+    Delayed(Duration),
+    /// With this value for all queries, e.g. to send malformed responses.
+    Fixed(Value),
+    /// With a JSON-RPC error for all queries.
+    Failing,
+}
+
+/// The state served by a `MockNode`, encoded the way the node stores it.
+#[derive(Clone, Debug, Default)]
+pub struct MockState {
+    // Values of `fetchExternalStateValueB64`, by address, query name and map index.
+    values: HashMap<(H160, String, Option<String>), Vec<u8>>,
+    // Values of `fetchBlockchainInfo`, by query name and arguments.
+    blockchain_info: HashMap<(String, String), String>,
+}
+
+impl MockState {
+    /// In Zil units.
+    pub fn balance(self, address: H160, balance: U256) -> Self {
+        self.value(
+            address,
+            "_balance",
+            None,
+            format!("\"{}\"", balance).into_bytes(),
+        )
+    }
+
+    pub fn nonce(self, address: H160, nonce: U256) -> Self {
+        self.value(
+            address,
+            "_nonce",
+            None,
+            format!("\"{}\"", nonce).into_bytes(),
+        )
+    }
+
+    pub fn code(self, address: H160, code: &[u8]) -> Self {
+        let code = format!("EVM{}", hex::encode(code)).into_bytes();
+        self.value(address, "_code", None, code)
+    }
+
+    pub fn storage(self, address: H160, key: H256, value: H256) -> Self {
+        let index = Some(format!("{:X}", key));
+        self.value(address, "_evm_storage", index, value.as_bytes().to_vec())
+    }
+
+    pub fn blockchain_info(mut self, query_name: &str, query_args: &str, value: &str) -> Self {
+        self.blockchain_info.insert(
+            (query_name.to_string(), query_args.to_string()),
+            value.to_string(),
+        );
+        self
+    }
+
+    fn value(mut self, address: H160, name: &str, index: Option<String>, bval: Vec<u8>) -> Self {
+        self.values.insert((address, name.to_string(), index), bval);
+        self
+    }
+
+    fn fetch_state_value(&self, args: &serde_json::Map<String, Value>) -> Result<Value> {
+        let address = H160::from_str(string_arg(args, "addr")?)
+            .map_err(|e| Error::invalid_params(format!("addr: {}", e)))?;
+        let query = base64::decode(string_arg(args, "query")?)
+            .ok()
+            .and_then(|query| ScillaMessage::ProtoScillaQuery::parse_from_bytes(&query).ok())
+            .ok_or_else(|| Error::invalid_params("query"))?;
+        let index = query
+            .get_indices()
+            .first()
+            .map(|index| String::from_utf8_lossy(index).into_owned());
+        let key = (address, query.get_name().to_string(), index);
+        Ok(match self.values.get(&key) {
+            Some(bval) => {
+                let mut value = ScillaMessage::ProtoScillaVal::new();
+                value.set_bval(bval.clone().into());
+                answer(true, base64::encode(value.write_to_bytes().unwrap()))
+            }
+            None => answer(false, String::new()),
+        })
+    }
+
+    fn fetch_blockchain_info(&self, args: &serde_json::Map<String, Value>) -> Result<Value> {
+        let key = (
+            string_arg(args, "query_name")?.to_string(),
+            string_arg(args, "query_args")?.to_string(),
+        );
+        Ok(match self.blockchain_info.get(&key) {
+            Some(value) => answer(true, value.clone()),
+            None => answer(false, String::new()),
+        })
+    }
+}
+
+fn string_arg<'a>(args: &'a serde_json::Map<String, Value>, name: &str) -> Result<&'a str> {
+    args.get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::invalid_params(format!("missing {}", name)))
+}
+
+// The node answers with whether the value was found, and the value.
+fn answer(found: bool, value: String) -> Value {
+    Value::Array(vec![Value::Bool(found), Value::String(value)])
+}
+
+type Method = fn(&MockState, &serde_json::Map<String, Value>) -> Result<Value>;
+
+/// A node serving a `MockState` on a socket of its own, until it is dropped.
+pub struct MockNode {
+    path: PathBuf,
+    server: Option<jsonrpc_ipc_server::Server>,
+    behaviour: Arc<Mutex<Behaviour>>,
+    calls: Arc<AtomicU64>,
+    connections: Arc<AtomicU64>,
+}
+
+impl MockNode {
+    pub fn start(state: MockState) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "evm-ds-mock-node-{}-{}.sock",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        Self::start_at(&path, state)
+    }
+
+    /// A node on the socket of one that was dropped, as if it restarted.
+    pub fn start_at(path: &Path, state: MockState) -> Self {
+        let path = path.to_path_buf();
+        let state = Arc::new(state);
+        let behaviour = Arc::new(Mutex::new(Behaviour::Normal));
+        let calls = Arc::new(AtomicU64::new(0));
+        let connections = Arc::new(AtomicU64::new(0));
+
+        let mut io = IoHandler::new();
+        let methods: [(&str, Method); 2] = [
+            ("fetchExternalStateValueB64", MockState::fetch_state_value),
+            ("fetchBlockchainInfo", MockState::fetch_blockchain_info),
+        ];
+        for (name, method) in methods {
+            let state = state.clone();
+            let behaviour = behaviour.clone();
+            let calls = calls.clone();
+            io.add_method(name, move |params: Params| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                let behaviour = behaviour.lock().unwrap().clone();
+                let result = params.parse().and_then(|args| method(&state, &args));
+                async move {
+                    match behaviour {
+                        Behaviour::Normal => result,
+                        Behaviour::Delayed(delay) => {
+                            tokio::time::sleep(delay).await;
+                            result
+                        }
+                        Behaviour::Fixed(value) => Ok(value),
+                        Behaviour::Failing => Err(Error::internal_error()),
+                    }
+                }
+            });
+        }
+        // Metadata is extracted once per connection.
+        let connected = connections.clone();
+        let server = jsonrpc_ipc_server::ServerBuilder::with_meta_extractor(
+            io,
+            move |_: &jsonrpc_ipc_server::RequestContext| {
+                connected.fetch_add(1, Ordering::Relaxed);
+            },
+        )
+        .start(&path.to_string_lossy())
+        .expect("Couldn't open mock node socket");
+        Self {
+            path,
+            server: Some(server),
+            behaviour,
+            calls,
+            connections,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn set_behaviour(&self, behaviour: Behaviour) {
+        *self.behaviour.lock().unwrap() = behaviour;
+    }
+
+    /// Number of queries received so far.
+    pub fn calls(&self) -> u64 {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    /// Number of connections opened so far.
+    pub fn connections(&self) -> u64 {
+        self.connections.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for MockNode {
+    fn drop(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.close();
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,403 @@
+/// Backend wrapper overriding account state and the environment for a single execution, like
+/// geth's `stateOverride` and `blockOverrides`.
+use std::collections::BTreeMap;
+
+use evm::backend::{Backend, Basic};
+use primitive_types::{H160, H256, U256};
+
+/// Overrides of a single account, as passed in requests.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AccountOverride {
+    /// Balance in Zil units, as stored by the node.
+    pub balance: Option<U256>,
+    pub nonce: Option<U256>,
+    /// EVM code in hex.
+    pub code: Option<String>,
+    /// Replaces the whole storage of the account: slots not listed here read as zero.
+    pub state: Option<BTreeMap<H256, H256>>,
+    /// Replaces individual storage slots, the rest is read from the node.
+    pub state_diff: Option<BTreeMap<H256, H256>>,
+}
+
+pub type StateOverrides = BTreeMap<H160, AccountOverride>;
+
+/// Overrides of the block environment, like geth's `blockOverrides`.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BlockOverrides {
+    pub number: Option<U256>,
+    pub time: Option<U256>,
+    pub coinbase: Option<H160>,
+    pub difficulty: Option<U256>,
+    pub gas_limit: Option<U256>,
+    pub base_fee: Option<U256>,
+}
+
+impl BlockOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.number.is_none()
+            && self.time.is_none()
+            && self.coinbase.is_none()
+            && self.difficulty.is_none()
+            && self.gas_limit.is_none()
+            && self.base_fee.is_none()
+    }
+}
+
+/// Overrides of the environment of an execution: its transaction and its block.
+#[derive(Clone, Debug, Default)]
+pub struct EnvOverrides {
+    pub origin: Option<H160>,
+    /// In Eth units.
+    pub gas_price: Option<U256>,
+    pub block: BlockOverrides,
+}
+
+impl EnvOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.origin.is_none() && self.gas_price.is_none() && self.block.is_empty()
+    }
+}
+
+// Validated overrides of an account, with the balance in Eth units.
+struct AccountState {
+    balance: Option<U256>,
+    nonce: Option<U256>,
+    code: Option<Vec<u8>>,
+    storage: Option<BTreeMap<H256, H256>>,
+    storage_diff: BTreeMap<H256, H256>,
+}
+
+// Backend consulting the overrides before falling back to the wrapped backend.
+pub struct OverrideBackend<'a, B> {
+    backend: &'a B,
+    accounts: BTreeMap<H160, AccountState>,
+    env: EnvOverrides,
+}
+
+impl<'a, B: Backend> OverrideBackend<'a, B> {
+    /// Wrap `backend`. `scale_balance` converts the overridden balances to Eth units.
+    pub fn new(
+        backend: &'a B,
+        overrides: StateOverrides,
+        env: EnvOverrides,
+        scale_balance: impl Fn(U256) -> U256,
+    ) -> Result<Self, String> {
+        let accounts = overrides
+            .into_iter()
+            .map(|(address, account)| {
+                if account.state.is_some() && account.state_diff.is_some() {
+                    return Err(format!(
+                        "account {:?} has both state and stateDiff overrides",
+                        address
+                    ));
+                }
+                let code = account
+                    .code
+                    .map(|code| {
+                        hex::decode(code.trim_start_matches("0x"))
+                            .map_err(|e| format!("code of account {:?}: {}", address, e))
+                    })
+                    .transpose()?;
+                Ok((
+                    address,
+                    AccountState {
+                        balance: account.balance.map(&scale_balance),
+                        nonce: account.nonce,
+                        code,
+                        storage: account.state,
+                        storage_diff: account.state_diff.unwrap_or_default(),
+                    },
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            backend,
+            accounts,
+            env,
+        })
+    }
+}
+
+impl<B: Backend> Backend for OverrideBackend<'_, B> {
+    fn gas_price(&self) -> U256 {
+        self.env
+            .gas_price
+            .unwrap_or_else(|| self.backend.gas_price())
+    }
+
+    fn origin(&self) -> H160 {
+        self.env.origin.unwrap_or_else(|| self.backend.origin())
+    }
+
+    fn block_hash(&self, number: U256) -> H256 {
+        self.backend.block_hash(number)
+    }
+
+    fn block_number(&self) -> U256 {
+        self.env
+            .block
+            .number
+            .unwrap_or_else(|| self.backend.block_number())
+    }
+
+    fn block_coinbase(&self) -> H160 {
+        self.env
+            .block
+            .coinbase
+            .unwrap_or_else(|| self.backend.block_coinbase())
+    }
+
+    fn block_timestamp(&self) -> U256 {
+        self.env
+            .block
+            .time
+            .unwrap_or_else(|| self.backend.block_timestamp())
+    }
+
+    fn block_difficulty(&self) -> U256 {
+        self.env
+            .block
+            .difficulty
+            .unwrap_or_else(|| self.backend.block_difficulty())
+    }
+
+    fn block_gas_limit(&self) -> U256 {
+        self.env
+            .block
+            .gas_limit
+            .unwrap_or_else(|| self.backend.block_gas_limit())
+    }
+
+    fn block_base_fee_per_gas(&self) -> U256 {
+        self.env
+            .block
+            .base_fee
+            .unwrap_or_else(|| self.backend.block_base_fee_per_gas())
+    }
+
+    fn chain_id(&self) -> U256 {
+        self.backend.chain_id()
+    }
+
+    fn exists(&self, address: H160) -> bool {
+        self.accounts.contains_key(&address) || self.backend.exists(address)
+    }
+
+    fn basic(&self, address: H160) -> Basic {
+        match self.accounts.get(&address) {
+            // Both are overridden, no need to ask the node.
+            Some(AccountState {
+                balance: Some(balance),
+                nonce: Some(nonce),
+                ..
+            }) => Basic {
+                balance: *balance,
+                nonce: *nonce,
+            },
+            Some(account) => {
+                let basic = self.backend.basic(address);
+                Basic {
+                    balance: account.balance.unwrap_or(basic.balance),
+                    nonce: account.nonce.unwrap_or(basic.nonce),
+                }
+            }
+            None => self.backend.basic(address),
+        }
+    }
+
+    fn code(&self, address: H160) -> Vec<u8> {
+        match self.accounts.get(&address).and_then(|a| a.code.as_ref()) {
+            Some(code) => code.clone(),
+            None => self.backend.code(address),
+        }
+    }
+
+    fn storage(&self, address: H160, key: H256) -> H256 {
+        match self.accounts.get(&address) {
+            Some(AccountState {
+                storage: Some(storage),
+                ..
+            }) => storage.get(&key).copied().unwrap_or_default(),
+            Some(account) => match account.storage_diff.get(&key) {
+                Some(value) => *value,
+                None => self.backend.storage(address, key),
+            },
+            None => self.backend.storage(address, key),
+        }
+    }
+
+    fn original_storage(&self, address: H160, key: H256) -> Option<H256> {
+        Some(self.storage(address, key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorybackend::{InMemoryBackend, MemoryAccount, MemoryState};
+    use crate::scillabackend::PrecisionPolicy;
+
+    fn slot(n: u64) -> H256 {
+        H256::from_low_u64_be(n)
+    }
+
+    // A node with the accounts 0x11..11 and 0x22..22, both with 10 Zil, nonce 1, the code
+    // 0x00 and the slots 1 = 10 and 2 = 20.
+    fn node() -> InMemoryBackend {
+        let account = MemoryAccount {
+            balance: U256::from(10),
+            nonce: U256::one(),
+            code: vec![0x00],
+            storage: [(slot(1), slot(10)), (slot(2), slot(20))]
+                .into_iter()
+                .collect(),
+        };
+        let mut state = MemoryState::default();
+        state
+            .accounts
+            .insert(H160::repeat_byte(0x11), account.clone());
+        state.accounts.insert(H160::repeat_byte(0x22), account);
+        InMemoryBackend::new(state, 1_000_000, PrecisionPolicy::Dust)
+    }
+
+    fn with_overrides<'a>(
+        node: &'a InMemoryBackend,
+        overrides: Vec<(H160, AccountOverride)>,
+    ) -> Result<OverrideBackend<'a, InMemoryBackend>, String> {
+        OverrideBackend::new(
+            node,
+            overrides.into_iter().collect(),
+            EnvOverrides::default(),
+            |balance| balance * 1_000_000,
+        )
+    }
+
+    #[test]
+    fn state_replaces_the_whole_storage_and_state_diff_single_slots() {
+        let node = node();
+        let (replaced, patched) = (H160::repeat_byte(0x11), H160::repeat_byte(0x22));
+        let backend = with_overrides(
+            &node,
+            vec![
+                (
+                    replaced,
+                    AccountOverride {
+                        state: Some([(slot(1), slot(11))].into_iter().collect()),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    patched,
+                    AccountOverride {
+                        state_diff: Some([(slot(1), slot(11))].into_iter().collect()),
+                        ..Default::default()
+                    },
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(backend.storage(replaced, slot(1)), slot(11));
+        assert_eq!(backend.storage(replaced, slot(2)), H256::zero());
+        assert_eq!(backend.storage(patched, slot(1)), slot(11));
+        assert_eq!(backend.storage(patched, slot(2)), slot(20));
+        assert_eq!(backend.original_storage(patched, slot(1)), Some(slot(11)));
+    }
+
+    #[test]
+    fn code_nonce_and_balance() {
+        let node = node();
+        let address = H160::repeat_byte(0x11);
+        let backend = with_overrides(
+            &node,
+            vec![(
+                address,
+                AccountOverride {
+                    nonce: Some(U256::from(5)),
+                    code: Some("0x6000".to_string()),
+                    ..Default::default()
+                },
+            )],
+        )
+        .unwrap();
+        assert_eq!(backend.code(address), vec![0x60, 0x00]);
+        // The balance is still the one of the node, in Eth units.
+        assert_eq!(
+            backend.basic(address),
+            Basic {
+                balance: U256::from(10_000_000),
+                nonce: U256::from(5),
+            }
+        );
+        // The storage is untouched.
+        assert_eq!(backend.storage(address, slot(1)), slot(10));
+
+        let backend = with_overrides(
+            &node,
+            vec![(
+                address,
+                AccountOverride {
+                    balance: Some(U256::from(3)),
+                    ..Default::default()
+                },
+            )],
+        )
+        .unwrap();
+        assert_eq!(
+            backend.basic(address),
+            Basic {
+                balance: U256::from(3_000_000),
+                nonce: U256::one(),
+            }
+        );
+        assert_eq!(backend.code(address), vec![0x00]);
+    }
+
+    #[test]
+    fn accounts_missing_on_the_node() {
+        let node = node();
+        let address = H160::repeat_byte(0x33);
+        assert!(!node.exists(address));
+        let backend = with_overrides(
+            &node,
+            vec![(
+                address,
+                AccountOverride {
+                    balance: Some(U256::from(1)),
+                    state_diff: Some([(slot(1), slot(11))].into_iter().collect()),
+                    ..Default::default()
+                },
+            )],
+        )
+        .unwrap();
+        assert!(backend.exists(address));
+        assert_eq!(
+            backend.basic(address),
+            Basic {
+                balance: U256::from(1_000_000),
+                nonce: U256::zero(),
+            }
+        );
+        assert!(backend.code(address).is_empty());
+        assert_eq!(backend.storage(address, slot(1)), slot(11));
+        assert_eq!(backend.storage(address, slot(2)), H256::zero());
+    }
+
+    #[test]
+    fn invalid_overrides() {
+        let node = node();
+        let address = H160::repeat_byte(0x11);
+        let both = AccountOverride {
+            state: Some(BTreeMap::new()),
+            state_diff: Some(BTreeMap::new()),
+            ..Default::default()
+        };
+        assert!(with_overrides(&node, vec![(address, both)]).is_err());
+        let code = AccountOverride {
+            code: Some("0xzz".to_string()),
+            ..Default::default()
+        };
+        assert!(with_overrides(&node, vec![(address, code)]).is_err());
+    }
+}
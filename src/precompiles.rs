@@ -1,19 +1,118 @@
-use evm::executor::stack::{PrecompileFailure, PrecompileOutput};
+use evm::executor::stack::{PrecompileFailure, PrecompileFn, PrecompileOutput};
 use evm::{Context, ExitError, ExitSucceed};
-use primitive_types::{H160, H256};
+use num_bigint::BigUint;
+use primitive_types::{H160, H256, U256};
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use crate::forks::Fork;
+use crate::metrics;
 
 const ECRECOVER_BASE: u64 = 3_000;
 const INPUT_LEN: usize = 128;
 
+const SHA256_BASE: u64 = 60;
+const SHA256_PER_WORD: u64 = 12;
+const RIPEMD160_BASE: u64 = 600;
+const RIPEMD160_PER_WORD: u64 = 120;
+const IDENTITY_BASE: u64 = 15;
+const IDENTITY_PER_WORD: u64 = 3;
+
+// EIP-198 divisor and EIP-2565 minimum for MODEXP.
+const MODEXP_GQUADDIVISOR: u128 = 20;
+const MODEXP_MIN_GAS: u128 = 200;
+
+// Istanbul (EIP-1108) prices of the alt_bn128 operations.
+const BN128_ADD_ISTANBUL: u64 = 150;
+const BN128_MUL_ISTANBUL: u64 = 6_000;
+const BN128_PAIRING_BASE_ISTANBUL: u64 = 45_000;
+const BN128_PAIRING_PER_POINT_ISTANBUL: u64 = 34_000;
+const BN128_PAIRING_ELEMENT_LEN: usize = 192;
+
+const BLAKE2F_INPUT_LEN: usize = 213;
+
 type Address = H160;
 
+/// Precompiled contracts available in a given fork, keyed by their address.
+pub(crate) fn for_fork(fork: Fork) -> BTreeMap<H160, PrecompileFn> {
+    let frontier = [
+        (1, ecrecover as PrecompileFn),
+        (2, sha256 as PrecompileFn),
+        (3, ripemd160 as PrecompileFn),
+        (4, identity as PrecompileFn),
+    ];
+    let istanbul = [
+        (6, bn128_add_istanbul as PrecompileFn),
+        (7, bn128_mul_istanbul as PrecompileFn),
+        (8, bn128_pairing_istanbul as PrecompileFn),
+        (9, blake2f as PrecompileFn),
+    ];
+    let modexp = match fork {
+        Fork::Frontier => vec![],
+        Fork::Istanbul => vec![(5, modexp_byzantium as PrecompileFn)],
+        Fork::Berlin | Fork::London => vec![(5, modexp_berlin as PrecompileFn)],
+    };
+    let istanbul = if fork >= Fork::Istanbul {
+        istanbul.to_vec()
+    } else {
+        vec![]
+    };
+    frontier
+        .into_iter()
+        .chain(modexp)
+        .chain(istanbul)
+        .map(|(address, precompile)| (H160::from_low_u64_be(address), precompile))
+        .collect()
+}
+
+fn check_gas(cost: u64, gas_limit: Option<u64>) -> Result<(), PrecompileFailure> {
+    match gas_limit {
+        Some(gas_limit) if cost > gas_limit => Err(PrecompileFailure::Error {
+            exit_status: ExitError::OutOfGas,
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn linear_cost(len: usize, base: u64, per_word: u64) -> u64 {
+    let words = (len as u64).saturating_add(31) / 32;
+    base.saturating_add(per_word.saturating_mul(words))
+}
+
+fn returned(cost: u64, output: Vec<u8>) -> Result<PrecompileOutput, PrecompileFailure> {
+    Ok(PrecompileOutput {
+        exit_status: ExitSucceed::Returned,
+        cost,
+        output,
+        logs: vec![],
+    })
+}
+
+fn failed(reason: &'static str) -> PrecompileFailure {
+    PrecompileFailure::Error {
+        exit_status: ExitError::Other(Cow::Borrowed(reason)),
+    }
+}
+
+/// `len` bytes of `input` starting at `offset`, right-padded with zeroes
+/// where the input is too short.
+fn read_padded(input: &[u8], offset: usize, len: usize) -> Vec<u8> {
+    let mut result = vec![0u8; len];
+    if offset < input.len() {
+        let available = &input[offset..];
+        let n = len.min(available.len());
+        result[..n].copy_from_slice(&available[..n]);
+    }
+    result
+}
+
 pub(crate) fn ecrecover(
     input: &[u8],
     gas_limit: Option<u64>,
     _contex: &Context,
     _is_static: bool,
 ) -> std::result::Result<PrecompileOutput, PrecompileFailure> {
+    metrics::count_precompile_call("ecrecover");
     let cost = ECRECOVER_BASE;
     if let Some(gas_limit) = gas_limit {
         if cost > gas_limit {
@@ -97,3 +196,551 @@ fn try_address_from_slice(raw_addr: &[u8]) -> Option<Address> {
     }
     Some(H160::from_slice(raw_addr))
 }
+
+pub(crate) fn sha256(
+    input: &[u8],
+    gas_limit: Option<u64>,
+    _context: &Context,
+    _is_static: bool,
+) -> std::result::Result<PrecompileOutput, PrecompileFailure> {
+    use sha2::Digest;
+
+    metrics::count_precompile_call("sha256");
+    let cost = linear_cost(input.len(), SHA256_BASE, SHA256_PER_WORD);
+    check_gas(cost, gas_limit)?;
+    returned(cost, sha2::Sha256::digest(input).to_vec())
+}
+
+pub(crate) fn ripemd160(
+    input: &[u8],
+    gas_limit: Option<u64>,
+    _context: &Context,
+    _is_static: bool,
+) -> std::result::Result<PrecompileOutput, PrecompileFailure> {
+    use ripemd::Digest;
+
+    metrics::count_precompile_call("ripemd160");
+    let cost = linear_cost(input.len(), RIPEMD160_BASE, RIPEMD160_PER_WORD);
+    check_gas(cost, gas_limit)?;
+    // The 20-byte hash is returned left-padded to a full word.
+    let mut output = vec![0u8; 12];
+    output.extend_from_slice(&ripemd::Ripemd160::digest(input));
+    returned(cost, output)
+}
+
+pub(crate) fn identity(
+    input: &[u8],
+    gas_limit: Option<u64>,
+    _context: &Context,
+    _is_static: bool,
+) -> std::result::Result<PrecompileOutput, PrecompileFailure> {
+    metrics::count_precompile_call("identity");
+    let cost = linear_cost(input.len(), IDENTITY_BASE, IDENTITY_PER_WORD);
+    check_gas(cost, gas_limit)?;
+    returned(cost, input.to_vec())
+}
+
+pub(crate) fn modexp_byzantium(
+    input: &[u8],
+    gas_limit: Option<u64>,
+    _context: &Context,
+    _is_static: bool,
+) -> std::result::Result<PrecompileOutput, PrecompileFailure> {
+    modexp(input, gas_limit, false)
+}
+
+pub(crate) fn modexp_berlin(
+    input: &[u8],
+    gas_limit: Option<u64>,
+    _context: &Context,
+    _is_static: bool,
+) -> std::result::Result<PrecompileOutput, PrecompileFailure> {
+    modexp(input, gas_limit, true)
+}
+
+/// MODEXP as specified by EIP-198, priced by EIP-2565 if `eip2565` is set.
+fn modexp(
+    input: &[u8],
+    gas_limit: Option<u64>,
+    eip2565: bool,
+) -> std::result::Result<PrecompileOutput, PrecompileFailure> {
+    metrics::count_precompile_call("modexp");
+    let base_len = U256::from_big_endian(&read_padded(input, 0, 32));
+    let exp_len = U256::from_big_endian(&read_padded(input, 32, 32));
+    let mod_len = U256::from_big_endian(&read_padded(input, 64, 32));
+
+    // Lengths this large can never be paid for, unless there is nothing to compute.
+    let max_len = base_len.max(mod_len);
+    let len_limit = U256::from(u32::MAX);
+    if max_len > len_limit || (!max_len.is_zero() && exp_len > len_limit) {
+        return Err(PrecompileFailure::Error {
+            exit_status: ExitError::OutOfGas,
+        });
+    }
+    let (base_len, mod_len, max_len) = (
+        base_len.as_usize(),
+        mod_len.as_usize(),
+        max_len.as_u64() as u128,
+    );
+    let exp_len = if max_len == 0 { 0 } else { exp_len.as_usize() };
+
+    // The number of iterations is estimated from the first 32 bytes of the exponent.
+    let exp_head = U256::from_big_endian(&read_padded(
+        input,
+        96usize.saturating_add(base_len),
+        exp_len.min(32),
+    ));
+    let head_bits = (exp_head.bits() as u128).saturating_sub(1);
+    let adjusted_exp_len = if exp_len <= 32 {
+        head_bits
+    } else {
+        8 * (exp_len as u128 - 32) + head_bits
+    };
+    let iterations = adjusted_exp_len.max(1);
+
+    let cost = if eip2565 {
+        let words = (max_len + 7) / 8;
+        (words * words * iterations / 3).max(MODEXP_MIN_GAS)
+    } else {
+        let complexity = if max_len <= 64 {
+            max_len * max_len
+        } else if max_len <= 1024 {
+            max_len * max_len / 4 + 96 * max_len - 3072
+        } else {
+            max_len * max_len / 16 + 480 * max_len - 199_680
+        };
+        complexity.saturating_mul(iterations) / MODEXP_GQUADDIVISOR
+    };
+    let cost = u64::try_from(cost).unwrap_or(u64::MAX);
+    check_gas(cost, gas_limit)?;
+
+    if mod_len == 0 {
+        return returned(cost, vec![]);
+    }
+    let base = BigUint::from_bytes_be(&read_padded(input, 96, base_len));
+    let exp = BigUint::from_bytes_be(&read_padded(input, 96 + base_len, exp_len));
+    let modulus = BigUint::from_bytes_be(&read_padded(input, 96 + base_len + exp_len, mod_len));
+
+    let mut output = vec![0u8; mod_len];
+    if modulus != BigUint::from(0u32) {
+        let result = base.modpow(&exp, &modulus).to_bytes_be();
+        output[mod_len - result.len()..].copy_from_slice(&result);
+    }
+    returned(cost, output)
+}
+
+fn read_fq(input: &[u8], offset: usize) -> Result<bn::Fq, PrecompileFailure> {
+    bn::Fq::from_slice(&read_padded(input, offset, 32)).map_err(|_| failed("ERR_BN128_INVALID_FQ"))
+}
+
+fn read_g1(input: &[u8], offset: usize) -> Result<bn::G1, PrecompileFailure> {
+    use bn::Group;
+
+    let x = read_fq(input, offset)?;
+    let y = read_fq(input, offset + 32)?;
+    if x.is_zero() && y.is_zero() {
+        // (0, 0) encodes the point at infinity.
+        Ok(bn::G1::zero())
+    } else {
+        bn::AffineG1::new(x, y)
+            .map(Into::into)
+            .map_err(|_| failed("ERR_BN128_INVALID_POINT"))
+    }
+}
+
+fn read_g2(input: &[u8], offset: usize) -> Result<bn::G2, PrecompileFailure> {
+    use bn::Group;
+
+    // Coefficients of Fq2 elements are encoded imaginary part first.
+    let x_im = read_fq(input, offset)?;
+    let x_re = read_fq(input, offset + 32)?;
+    let y_im = read_fq(input, offset + 64)?;
+    let y_re = read_fq(input, offset + 96)?;
+    let (x, y) = (bn::Fq2::new(x_re, x_im), bn::Fq2::new(y_re, y_im));
+    if x.is_zero() && y.is_zero() {
+        Ok(bn::G2::zero())
+    } else {
+        bn::AffineG2::new(x, y)
+            .map(Into::into)
+            .map_err(|_| failed("ERR_BN128_INVALID_POINT"))
+    }
+}
+
+fn encode_g1(point: bn::G1) -> Vec<u8> {
+    let mut output = vec![0u8; 64];
+    // The point at infinity has no affine form and is encoded as (0, 0).
+    if let Some(point) = bn::AffineG1::from_jacobian(point) {
+        point.x().to_big_endian(&mut output[0..32]).unwrap();
+        point.y().to_big_endian(&mut output[32..64]).unwrap();
+    }
+    output
+}
+
+pub(crate) fn bn128_add_istanbul(
+    input: &[u8],
+    gas_limit: Option<u64>,
+    _context: &Context,
+    _is_static: bool,
+) -> std::result::Result<PrecompileOutput, PrecompileFailure> {
+    metrics::count_precompile_call("bn128_add");
+    let cost = BN128_ADD_ISTANBUL;
+    check_gas(cost, gas_limit)?;
+    let p1 = read_g1(input, 0)?;
+    let p2 = read_g1(input, 64)?;
+    returned(cost, encode_g1(p1 + p2))
+}
+
+pub(crate) fn bn128_mul_istanbul(
+    input: &[u8],
+    gas_limit: Option<u64>,
+    _context: &Context,
+    _is_static: bool,
+) -> std::result::Result<PrecompileOutput, PrecompileFailure> {
+    metrics::count_precompile_call("bn128_mul");
+    let cost = BN128_MUL_ISTANBUL;
+    check_gas(cost, gas_limit)?;
+    let point = read_g1(input, 0)?;
+    // Any 256-bit scalar is allowed, reduce it modulo the group order.
+    let scalar = bn::arith::U256::from_slice(&read_padded(input, 64, 32))
+        .map(bn::Fr::new_mul_factor)
+        .map_err(|_| failed("ERR_BN128_INVALID_FR"))?;
+    returned(cost, encode_g1(point * scalar))
+}
+
+pub(crate) fn bn128_pairing_istanbul(
+    input: &[u8],
+    gas_limit: Option<u64>,
+    _context: &Context,
+    _is_static: bool,
+) -> std::result::Result<PrecompileOutput, PrecompileFailure> {
+    metrics::count_precompile_call("bn128_pairing");
+    if input.len() % BN128_PAIRING_ELEMENT_LEN != 0 {
+        return Err(failed("ERR_BN128_PAIRING_INPUT_LEN"));
+    }
+    let pairs = input.len() / BN128_PAIRING_ELEMENT_LEN;
+    let cost = BN128_PAIRING_BASE_ISTANBUL
+        .saturating_add(BN128_PAIRING_PER_POINT_ISTANBUL.saturating_mul(pairs as u64));
+    check_gas(cost, gas_limit)?;
+
+    let pairs = input
+        .chunks(BN128_PAIRING_ELEMENT_LEN)
+        .map(|chunk| Ok((read_g1(chunk, 0)?, read_g2(chunk, 64)?)))
+        .collect::<Result<Vec<_>, PrecompileFailure>>()?;
+    let mut output = vec![0u8; 32];
+    if bn::pairing_batch(&pairs) == bn::Gt::one() {
+        output[31] = 1;
+    }
+    returned(cost, output)
+}
+
+const BLAKE2B_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+const BLAKE2B_SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+fn blake2b_mix(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+/// The BLAKE2b compression function F with a configurable number of rounds (EIP-152).
+fn blake2b_compress(h: &mut [u64; 8], m: &[u64; 16], t: [u64; 2], last: bool, rounds: u32) {
+    let mut v = [0u64; 16];
+    v[..8].copy_from_slice(h);
+    v[8..].copy_from_slice(&BLAKE2B_IV);
+    v[12] ^= t[0];
+    v[13] ^= t[1];
+    if last {
+        v[14] = !v[14];
+    }
+    for round in 0..rounds as usize {
+        let s = &BLAKE2B_SIGMA[round % 10];
+        blake2b_mix(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        blake2b_mix(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        blake2b_mix(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        blake2b_mix(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        blake2b_mix(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        blake2b_mix(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+        blake2b_mix(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+        blake2b_mix(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+    for (i, word) in h.iter_mut().enumerate() {
+        *word ^= v[i] ^ v[i + 8];
+    }
+}
+
+pub(crate) fn blake2f(
+    input: &[u8],
+    gas_limit: Option<u64>,
+    _context: &Context,
+    _is_static: bool,
+) -> std::result::Result<PrecompileOutput, PrecompileFailure> {
+    metrics::count_precompile_call("blake2f");
+    if input.len() != BLAKE2F_INPUT_LEN {
+        return Err(failed("ERR_BLAKE2F_INPUT_LEN"));
+    }
+    let mut rounds = [0u8; 4];
+    rounds.copy_from_slice(&input[0..4]);
+    let rounds = u32::from_be_bytes(rounds);
+    let cost = rounds as u64;
+    check_gas(cost, gas_limit)?;
+
+    let read_u64 = |offset: usize| {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&input[offset..offset + 8]);
+        u64::from_le_bytes(word)
+    };
+    let mut h = [0u64; 8];
+    for (i, word) in h.iter_mut().enumerate() {
+        *word = read_u64(4 + i * 8);
+    }
+    let mut m = [0u64; 16];
+    for (i, word) in m.iter_mut().enumerate() {
+        *word = read_u64(68 + i * 8);
+    }
+    let t = [read_u64(196), read_u64(204)];
+    let last = match input[212] {
+        0 => false,
+        1 => true,
+        _ => return Err(failed("ERR_BLAKE2F_FINAL_FLAG")),
+    };
+
+    blake2b_compress(&mut h, &m, t, last, rounds);
+    returned(cost, h.iter().flat_map(|word| word.to_le_bytes()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> Context {
+        Context {
+            address: H160::zero(),
+            caller: H160::zero(),
+            apparent_value: U256::zero(),
+        }
+    }
+
+    fn run(precompile: PrecompileFn, input: &str) -> (u64, String) {
+        let output = precompile(
+            &hex::decode(input).unwrap(),
+            Some(u64::MAX),
+            &context(),
+            false,
+        )
+        .unwrap_or_else(|_| panic!("precompile failed on {}", input));
+        (output.cost, hex::encode(output.output))
+    }
+
+    fn fails(precompile: PrecompileFn, input: &str, gas_limit: u64) -> bool {
+        precompile(
+            &hex::decode(input).unwrap(),
+            Some(gas_limit),
+            &context(),
+            false,
+        )
+        .is_err()
+    }
+
+    #[test]
+    fn fork_precompile_sets() {
+        assert_eq!(for_fork(Fork::Frontier).len(), 4);
+        assert_eq!(for_fork(Fork::Istanbul).len(), 9);
+        assert_eq!(for_fork(Fork::London).len(), 9);
+        assert!(for_fork(Fork::London).contains_key(&H160::from_low_u64_be(9)));
+    }
+
+    #[test]
+    fn hashes_and_identity() {
+        assert_eq!(
+            run(sha256, ""),
+            (
+                60,
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string()
+            )
+        );
+        assert_eq!(
+            run(ripemd160, ""),
+            (
+                600,
+                "0000000000000000000000009c1185a5c5e9fc54612808977ee8f548b2258d31".to_string()
+            )
+        );
+        assert_eq!(run(identity, "0102030405"), (18, "0102030405".to_string()));
+        assert!(fails(sha256, "00", 71));
+    }
+
+    // Example from EIP-198: 3 ** (p - 2) mod p, where p is the secp256k1 field prime.
+    const MODEXP_FERMAT: &str = concat!(
+        "0000000000000000000000000000000000000000000000000000000000000001",
+        "0000000000000000000000000000000000000000000000000000000000000020",
+        "0000000000000000000000000000000000000000000000000000000000000020",
+        "03",
+        "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e",
+        "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f",
+    );
+
+    #[test]
+    fn modexp_eip198_and_eip2565_pricing() {
+        let one = "0000000000000000000000000000000000000000000000000000000000000001";
+        assert_eq!(
+            run(modexp_byzantium, MODEXP_FERMAT),
+            (13056, one.to_string())
+        );
+        assert_eq!(run(modexp_berlin, MODEXP_FERMAT), (1360, one.to_string()));
+        // Zero modulus yields zeroes, empty lengths cost the EIP-2565 minimum.
+        assert_eq!(
+            run(
+                modexp_berlin,
+                concat!(
+                    "0000000000000000000000000000000000000000000000000000000000000001",
+                    "0000000000000000000000000000000000000000000000000000000000000001",
+                    "0000000000000000000000000000000000000000000000000000000000000002",
+                    "0203",
+                    "0000",
+                )
+            ),
+            (200, "0000".to_string())
+        );
+        assert_eq!(run(modexp_berlin, ""), (200, "".to_string()));
+        // Absurd lengths must not be attempted.
+        assert!(fails(
+            modexp_berlin,
+            concat!(
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            ),
+            u64::MAX
+        ));
+    }
+
+    const BN128_G1: &str = concat!(
+        "0000000000000000000000000000000000000000000000000000000000000001",
+        "0000000000000000000000000000000000000000000000000000000000000002",
+    );
+    const BN128_G1_NEG: &str = concat!(
+        "0000000000000000000000000000000000000000000000000000000000000001",
+        "30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd45",
+    );
+    const BN128_G1_DOUBLE: &str = concat!(
+        "030644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd3",
+        "15ed738c0e0a7c92e7845f96b2ae9c0a68a6a449e3538fc7ff3ebf7a5a18a2c4",
+    );
+    const BN128_G2: &str = concat!(
+        "198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c2",
+        "1800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed",
+        "090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b",
+        "12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa",
+    );
+
+    #[test]
+    fn bn128_add_and_mul() {
+        assert_eq!(
+            run(bn128_add_istanbul, &format!("{}{}", BN128_G1, BN128_G1)),
+            (150, BN128_G1_DOUBLE.to_string())
+        );
+        assert_eq!(
+            run(bn128_add_istanbul, &format!("{}{}", BN128_G1, BN128_G1_NEG)),
+            (150, "00".repeat(64))
+        );
+        assert_eq!(
+            run(bn128_mul_istanbul, &format!("{}{:064x}", BN128_G1, 2)),
+            (6000, BN128_G1_DOUBLE.to_string())
+        );
+        // (1, 1) is not on the curve.
+        assert!(fails(
+            bn128_add_istanbul,
+            &format!("{:064x}{:064x}", 1, 1),
+            u64::MAX
+        ));
+    }
+
+    #[test]
+    fn bn128_pairing() {
+        let true_word = format!("{:064x}", 1);
+        assert_eq!(run(bn128_pairing_istanbul, ""), (45000, true_word.clone()));
+        // e(P, Q) * e(-P, Q) == 1
+        assert_eq!(
+            run(
+                bn128_pairing_istanbul,
+                &format!("{}{}{}{}", BN128_G1, BN128_G2, BN128_G1_NEG, BN128_G2)
+            ),
+            (113000, true_word)
+        );
+        assert_eq!(
+            run(bn128_pairing_istanbul, &format!("{}{}", BN128_G1, BN128_G2)),
+            (79000, "00".repeat(32))
+        );
+        assert!(fails(bn128_pairing_istanbul, "00", u64::MAX));
+    }
+
+    // Test vectors 4 and 5 from EIP-152, differing only in the number of rounds.
+    fn blake2f_input(rounds: u32) -> String {
+        format!(
+            "{:08x}{}{}616263{}{}{}",
+            rounds,
+            "48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5",
+            "d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e1319cde05b",
+            "00".repeat(125),
+            "03000000000000000000000000000000",
+            "01"
+        )
+    }
+
+    #[test]
+    fn blake2f_eip152() {
+        assert_eq!(
+            run(blake2f, &blake2f_input(0)),
+            (
+                0,
+                concat!(
+                    "08c9bcf367e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f3af54fa5",
+                    "d282e6ad7f520e511f6c3e2b8c68059b9442be0454267ce079217e1319cde05b",
+                )
+                .to_string()
+            )
+        );
+        assert_eq!(
+            run(blake2f, &blake2f_input(12)),
+            (
+                12,
+                concat!(
+                    "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1",
+                    "7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923",
+                )
+                .to_string()
+            )
+        );
+        // Wrong length and a final block flag other than 0 or 1 are errors.
+        assert!(fails(blake2f, &blake2f_input(12)[2..], u64::MAX));
+        let bad_flag = format!("{}02", &blake2f_input(12)[..424]);
+        assert!(fails(blake2f, &bad_flag, u64::MAX));
+        assert!(fails(blake2f, &blake2f_input(12), 11));
+    }
+}
@@ -0,0 +1,122 @@
+/// Recordings of executions, one JSON object per line, to replay them without the node.
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use jsonrpc_core::{serde_json, Value};
+use log::error;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Appends entries to a recording, from any thread.
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Failing to record does not fail the execution, it is only logged.
+    pub fn record(&self, entry: &impl Serialize) {
+        match serde_json::to_string(entry) {
+            Ok(line) => {
+                if let Err(e) = writeln!(self.file.lock().unwrap(), "{}", line) {
+                    error!("Cannot write recording: {}", e);
+                }
+            }
+            Err(e) => error!("Cannot serialize recording: {}", e),
+        }
+    }
+}
+
+pub fn read<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, String> {
+    let recording =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    recording
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("{}:{}: {}", path.display(), index + 1, e))
+        })
+        .collect()
+}
+
+/// The differences between `recorded` and `replayed`, one per line, by their path in
+/// the JSON documents, e.g. `.Ok.apply[0].balance: 12 != 13`.
+pub fn diff(recorded: &Value, replayed: &Value) -> Vec<String> {
+    let mut differences = vec![];
+    diff_at("", recorded, replayed, &mut differences);
+    differences
+}
+
+fn diff_at(path: &str, recorded: &Value, replayed: &Value, differences: &mut Vec<String>) {
+    match (recorded, replayed) {
+        (Value::Object(recorded), Value::Object(replayed)) => {
+            for (key, value) in recorded {
+                let path = format!("{}.{}", path, key);
+                diff_at(
+                    &path,
+                    value,
+                    replayed.get(key).unwrap_or(&Value::Null),
+                    differences,
+                );
+            }
+            for (key, value) in replayed {
+                if !recorded.contains_key(key) {
+                    diff_at(
+                        &format!("{}.{}", path, key),
+                        &Value::Null,
+                        value,
+                        differences,
+                    );
+                }
+            }
+        }
+        (Value::Array(recorded), Value::Array(replayed)) if recorded.len() == replayed.len() => {
+            for (index, (recorded, replayed)) in recorded.iter().zip(replayed).enumerate() {
+                diff_at(
+                    &format!("{}[{}]", path, index),
+                    recorded,
+                    replayed,
+                    differences,
+                );
+            }
+        }
+        _ if recorded != replayed => {
+            differences.push(format!("{}: {} != {}", path, recorded, replayed));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpc_core::serde_json::json;
+
+    #[test]
+    fn diff_by_path() {
+        let recorded = json!({"Ok": {"apply": [{"balance": 12}, {"nonce": 1}], "logs": []}});
+        let replayed = json!({"Ok": {"apply": [{"balance": 13}, {"nonce": 1}], "gas": 5}});
+        assert_eq!(
+            diff(&recorded, &replayed),
+            vec![
+                ".Ok.apply[0].balance: 12 != 13",
+                ".Ok.logs: [] != null",
+                ".Ok.gas: null != 5",
+            ]
+        );
+        assert!(diff(&recorded, &recorded).is_empty());
+    }
+}
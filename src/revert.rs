@@ -0,0 +1,113 @@
+/// Decoding of the data returned by REVERT, as produced by Solidity.
+use primitive_types::U256;
+
+/// Selector of `Error(string)`, used by `revert("...")` and `require(..., "...")`.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// Selector of `Panic(uint256)`, used by failed assertions and checked arithmetic.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// The human-readable reason of a revert. Custom errors cannot be decoded without
+/// the ABI of the contract, so only their selector is given. `None` if there is no data.
+pub fn decode_revert_reason(data: &[u8]) -> Option<String> {
+    if let Some(args) = data.strip_prefix(&ERROR_SELECTOR) {
+        if let Some(reason) = decode_error(args) {
+            return Some(reason);
+        }
+    }
+    if let Some(args) = data.strip_prefix(&PANIC_SELECTOR) {
+        if let Some(code) = read_word(args, 0) {
+            return Some(format!("panic: {} ({:#x})", panic_description(code), code));
+        }
+    }
+    data.get(..4)
+        .map(|selector| format!("custom error 0x{}", hex::encode(selector)))
+}
+
+fn decode_error(args: &[u8]) -> Option<String> {
+    let offset = read_usize(args, 0)?;
+    let length = read_usize(args, offset)?;
+    let start = offset.checked_add(32)?;
+    let reason = args.get(start..start.checked_add(length)?)?;
+    Some(String::from_utf8_lossy(reason).into_owned())
+}
+
+/// See "Panic via assert and Error via require" in the Solidity documentation.
+fn panic_description(code: U256) -> &'static str {
+    if code > U256::from(u8::MAX) {
+        return "unknown panic code";
+    }
+    match code.low_u32() {
+        0x00 => "generic compiler panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array encoding",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to uninitialized function",
+        _ => "unknown panic code",
+    }
+}
+
+fn read_word(args: &[u8], offset: usize) -> Option<U256> {
+    let word = args.get(offset..offset.checked_add(32)?)?;
+    Some(U256::from_big_endian(word))
+}
+
+// Reads the ABI word at `offset` as a length or an offset into `args`.
+fn read_usize(args: &[u8], offset: usize) -> Option<usize> {
+    let value = read_word(args, offset)?;
+    (value <= U256::from(usize::MAX)).then(|| value.as_usize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_string() {
+        // revert("Not enough Ether provided.")
+        let data = hex::decode(concat!(
+            "08c379a0",
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "000000000000000000000000000000000000000000000000000000000000001a",
+            "4e6f7420656e6f7567682045746865722070726f76696465642e000000000000",
+        ))
+        .unwrap();
+        assert_eq!(
+            decode_revert_reason(&data).as_deref(),
+            Some("Not enough Ether provided.")
+        );
+    }
+
+    #[test]
+    fn panic_code() {
+        let data = hex::decode(concat!(
+            "4e487b71",
+            "0000000000000000000000000000000000000000000000000000000000000011",
+        ))
+        .unwrap();
+        assert_eq!(
+            decode_revert_reason(&data).as_deref(),
+            Some("panic: arithmetic overflow or underflow (0x11)")
+        );
+    }
+
+    #[test]
+    fn custom_and_empty() {
+        let data = hex::decode("cafebabe0000").unwrap();
+        assert_eq!(
+            decode_revert_reason(&data).as_deref(),
+            Some("custom error 0xcafebabe")
+        );
+        // A truncated Error(string) is reported by its selector.
+        assert_eq!(
+            decode_revert_reason(&ERROR_SELECTOR).as_deref(),
+            Some("custom error 0x08c379a0")
+        );
+        assert_eq!(decode_revert_reason(&[]), None);
+    }
+}
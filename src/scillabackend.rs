@@ -1,19 +1,25 @@
 /// Backend implementation that stores EVM state via the Scilla JSONRPC interface.
-use std::path::PathBuf;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use evm::backend::{Backend, Basic};
 use jsonrpc_core::serde_json;
 use jsonrpc_core::types::params::Params;
-use jsonrpc_core::{Error, Result, Value};
-use jsonrpc_core_client::RawClient;
+use jsonrpc_core::Value;
+use jsonrpc_core_client::{RawClient, RpcError};
 use primitive_types::{H160, H256, U256};
 
 use log::{debug, info};
 
 use protobuf::Message;
 
+use crate::error::EvmError;
 use crate::ipc_connect;
+use crate::metrics;
 use crate::protos::ScillaMessage;
 
 /// Chain ID base for all Zilliqa-based EVM chains. Needed to avoid
@@ -21,17 +27,185 @@ use crate::protos::ScillaMessage;
 /// See https://zilliqa-jira.atlassian.net/browse/ZIL-4668
 const BASE_CHAIN_ID: u64 = 33000;
 
+/// How long to wait for an answer of the node.
+const NODE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many idle connections to the node are kept for the next executions.
+const MAX_IDLE_CONNECTIONS: usize = 16;
+
 #[derive(Clone)]
 pub struct ScillaBackendConfig {
     // Path to the Unix domain socket over which we talk to the Node.
     pub path: PathBuf,
     // Scaling factor of Eth <-> Zil. Should be either 1 or 1_000_000.
     pub zil_scaling_factor: u64,
+    // What to do with balances that are not a whole number of Zil units.
+    pub precision_policy: PrecisionPolicy,
+    // Idle connections to the node, shared by the backends of all clones of this config.
+    pub connections: NodePool,
+}
+
+/// Connections to the node kept open across executions, so that an execution does not pay
+/// for connecting. A backend takes one on its first query, or opens a new one if none is
+/// idle, and gives it back when it is dropped.
+#[derive(Clone, Default)]
+pub struct NodePool(Arc<Mutex<Vec<NodeConnection>>>);
+
+impl NodePool {
+    fn take(&self) -> Option<NodeConnection> {
+        self.0.lock().unwrap().pop()
+    }
+
+    fn give_back(&self, connection: NodeConnection) {
+        let mut idle = self.0.lock().unwrap();
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(connection);
+        }
+    }
+}
+
+/// How balances in Eth units are converted to Zil units when they are not a multiple
+/// of the scaling factor, e.g. after a contract transferred an odd amount of wei.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrecisionPolicy {
+    /// Round down, losing the remainder.
+    Truncate,
+    /// Fail the execution.
+    Reject,
+    /// Round down, and report the remainder as `dust` with the balance.
+    Dust,
+}
+
+impl FromStr for PrecisionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "truncate" => Ok(PrecisionPolicy::Truncate),
+            "reject" => Ok(PrecisionPolicy::Reject),
+            "dust" => Ok(PrecisionPolicy::Dust),
+            _ => Err(format!("unknown precision policy '{}'", s)),
+        }
+    }
+}
+
+/// A backend of Zilliqa state. The node keeps balances in Zil units, which the EVM sees
+/// in Eth units, scaled by the Zil scaling factor.
+pub trait ZilliqaBackend: Backend {
+    fn zil_scaling_factor(&self) -> u64;
+
+    fn precision_policy(&self) -> PrecisionPolicy;
+
+    /// Splits `eth` into whole Zil units and the remainder in Eth units.
+    fn split_eth_to_zil(&self, eth: U256) -> (U256, U256) {
+        eth.div_mod(self.zil_scaling_factor().into())
+    }
+
+    fn scale_zil_to_eth(&self, zil: U256) -> U256 {
+        zil * self.zil_scaling_factor()
+    }
 }
 
 // Backend relying on Scilla variables and Scilla JSONRPC interface.
 pub struct ScillaBackend {
     config: ScillaBackendConfig,
+    // Connection to the node, taken from the pool on first use and used by all queries of
+    // this backend, until it is given back when the backend is dropped.
+    connection: RefCell<Option<NodeConnection>>,
+    // Answers of the node for this execution. The node state does not change while
+    // we execute, and our own writes are kept by the executor, not in the backend.
+    cache: QueryCache,
+    stats: IpcStats,
+    // Queries to the node and their outcomes, when recording.
+    recording: Option<Arc<Mutex<Vec<NodeQuery>>>>,
+    // Outcomes of the queries of a recording, by method and arguments, answered instead
+    // of querying the node.
+    replay: Option<HashMap<(String, String), Result<Option<Value>, EvmError>>>,
+}
+
+/// A query to the node and its outcome, as recorded to replay an execution without the node.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct NodeQuery {
+    pub method: String,
+    pub args: serde_json::Map<String, Value>,
+    /// `None` if the node answered with an error.
+    pub outcome: Result<Option<Value>, EvmError>,
+}
+
+type StateQuery = (H160, String, Option<H256>, bool);
+
+#[derive(Default)]
+struct QueryCache {
+    state: RefCell<HashMap<StateQuery, Option<ScillaMessage::ProtoScillaVal>>>,
+    blockchain_info: RefCell<HashMap<(String, Option<String>), Value>>,
+}
+
+// A client connected to the node, with its own runtime driving the connection.
+struct NodeConnection {
+    client: RawClient,
+    // Only taken when dropped.
+    rt: Option<tokio::runtime::Runtime>,
+}
+
+impl NodeConnection {
+    fn connect(path: &Path) -> Result<Self, EvmError> {
+        // Within this runtime, we need a separate runtime just to handle all JSON
+        // client operations. The runtime will then drop and close all connections
+        // and release all resources. Also when the thread panics.
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let client: RawClient = rt
+            .block_on(ipc_connect::ipc_connect(path))
+            .map_err(|e| EvmError::NodeUnreachable(format!("{}: {}", path.display(), e)))?;
+        Ok(Self {
+            client,
+            rt: Some(rt),
+        })
+    }
+
+    fn call(
+        &self,
+        method: &str,
+        args: serde_json::Map<String, Value>,
+    ) -> Result<Result<Value, RpcError>, tokio::time::error::Elapsed> {
+        let rt = self.rt.as_ref().unwrap();
+        rt.block_on(tokio::time::timeout(
+            NODE_TIMEOUT,
+            self.client.call_method(method, Params::Map(args)),
+        ))
+    }
+}
+
+impl Drop for NodeConnection {
+    fn drop(&mut self) {
+        // The last clone of a pool may be dropped in an async context, where a runtime
+        // cannot block on its shutdown. Dropping the tasks closes the connection anyway.
+        if let Some(rt) = self.rt.take() {
+            rt.shutdown_background();
+        }
+    }
+}
+
+#[derive(Default)]
+struct IpcStats {
+    calls: Cell<u64>,
+    connections: Cell<u64>,
+    time: Cell<Duration>,
+    cache_hits: Cell<u64>,
+    cache_misses: Cell<u64>,
+}
+
+impl IpcStats {
+    fn count_cache_lookup(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.set(counter.get() + 1);
+    }
 }
 
 // Adding some convenience to ProtoScillaVal to convert to U256 and bytes.
@@ -57,63 +231,162 @@ impl ScillaMessage::ProtoScillaVal {
 
 impl ScillaBackend {
     pub fn new(config: ScillaBackendConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            connection: RefCell::new(None),
+            cache: QueryCache::default(),
+            stats: IpcStats::default(),
+            recording: None,
+            replay: None,
+        }
+    }
+
+    /// Records all queries to the node, with their outcomes, in `queries`.
+    pub fn with_recording(mut self, queries: Arc<Mutex<Vec<NodeQuery>>>) -> Self {
+        self.recording = Some(queries);
+        self
+    }
+
+    /// A backend answering the `queries` of a recording, without a node.
+    pub fn replaying(config: ScillaBackendConfig, queries: Vec<NodeQuery>) -> Self {
+        let mut backend = Self::new(config);
+        backend.replay = Some(
+            queries
+                .into_iter()
+                .map(|query| (replay_key(&query.method, &query.args), query.outcome))
+                .collect(),
+        );
+        backend
     }
 
-    // Call the Scilla IPC Server API.
+    // Call the Scilla IPC Server API. Returns `None` if the node answered with an error.
+    // `query_name` is the state or blockchain info queried, for the metrics.
     fn call_ipc_server_api(
         &self,
         method: &str,
+        query_name: &str,
+        args: serde_json::Map<String, Value>,
+    ) -> Result<Option<Value>, EvmError> {
+        if let Some(replay) = &self.replay {
+            return replay
+                .get(&replay_key(method, &args))
+                .cloned()
+                .unwrap_or_else(|| {
+                    Err(EvmError::NodeUnreachable(format!(
+                        "{} {} is not in the recording",
+                        method,
+                        Value::Object(args)
+                    )))
+                });
+        }
+        let outcome = self.query_node(method, query_name, args.clone());
+        if let Some(recording) = &self.recording {
+            recording.lock().unwrap().push(NodeQuery {
+                method: method.to_string(),
+                args,
+                outcome: outcome.clone(),
+            });
+        }
+        outcome
+    }
+
+    fn query_node(
+        &self,
+        method: &str,
+        query_name: &str,
         args: serde_json::Map<String, Value>,
-    ) -> Result<Value> {
+    ) -> Result<Option<Value>, EvmError> {
         debug!("call_ipc_server_api: {}, {:?}", method, args);
-        // Within this runtime, we need a separate runtime just to handle all JSON
-        // client operations. The runtime will then drop and close all connections
-        // and release all resources. Also when the thread panics.
-        let rt = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .unwrap();
-        let call_with_timeout = rt.block_on(async move {
-            let client: RawClient = ipc_connect::ipc_connect(&self.config.path)
-                .await
-                .expect("Failed to connect to the node Unix domain socket");
-            tokio::time::timeout(
-                tokio::time::Duration::from_secs(2), // Require response in 2 secs max.
-                client.call_method(method, Params::Map(args)),
-            )
-            .await
-        });
-        if let Ok(result) = call_with_timeout {
-            result.map_err(|_| Error::internal_error())
-        } else {
-            panic!("timeout calling {}", method);
+        let start = Instant::now();
+        let mut connection = self.connection.borrow_mut();
+        let mut reconnected = false;
+        let result = loop {
+            if connection.is_none() && !reconnected {
+                *connection = self.config.connections.take();
+            }
+            if connection.is_none() {
+                self.stats.connections.set(self.stats.connections.get() + 1);
+                match NodeConnection::connect(&self.config.path) {
+                    Ok(node) => *connection = Some(node),
+                    Err(e) => {
+                        metrics::observe_node_query(query_name, start.elapsed(), false);
+                        return Err(e);
+                    }
+                }
+            }
+            let call_with_timeout = connection.as_ref().unwrap().call(method, args.clone());
+            match call_with_timeout {
+                // The node may have closed the connection since the last call, possibly
+                // in an earlier execution. All queries are reads, so it is safe to
+                // reconnect and try again once.
+                Ok(Err(e)) if !reconnected && !matches!(e, RpcError::JsonRpcError(_)) => {
+                    debug!("call_ipc_server_api: reconnecting after {:?}", e);
+                    *connection = None;
+                    reconnected = true;
+                }
+                Ok(result) => break result,
+                Err(_) => {
+                    // The connection may still deliver the late answer, do not reuse it.
+                    *connection = None;
+                    metrics::observe_node_query(query_name, start.elapsed(), true);
+                    return Err(EvmError::Timeout(method.to_string()));
+                }
+            }
+        };
+        self.stats.calls.set(self.stats.calls.get() + 1);
+        self.stats.time.set(self.stats.time.get() + start.elapsed());
+        metrics::observe_node_query(query_name, start.elapsed(), false);
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(RpcError::JsonRpcError(e)) => {
+                debug!("call_ipc_server_api: {} failed: {:?}", method, e);
+                Ok(None)
+            }
+            Err(e) => {
+                *connection = None;
+                Err(EvmError::NodeUnreachable(format!("{}: {}", method, e)))
+            }
         }
     }
 
-    fn query_jsonrpc(&self, query_name: &str, query_args: Option<&str>) -> Value {
+    fn query_jsonrpc(&self, query_name: &str, query_args: Option<&str>) -> Result<Value, EvmError> {
+        let cache_key = (query_name.to_string(), query_args.map(str::to_string));
+        if let Some(value) = self.cache.blockchain_info.borrow().get(&cache_key) {
+            self.stats.count_cache_lookup(true);
+            return Ok(value.clone());
+        }
+        self.stats.count_cache_lookup(false);
+
         info!("query_jsonrpc: {}, {:?}", query_name, query_args);
         // Make a JSON Query for fetchBlockchaininfo
         let mut args = serde_json::Map::new();
         args.insert("query_name".into(), query_name.into());
         args.insert("query_args".into(), query_args.unwrap_or_default().into());
-        let result = self
-            .call_ipc_server_api("fetchBlockchainInfo", args)
-            .unwrap_or_default();
-        // Check that the call succeeded.
-        let null = Value::Null;
-        let succeeded = result.get(0).unwrap_or(&null).as_bool().unwrap_or_default();
-
-        if succeeded {
-            // Check that there is a result of a given type.
-            result.get(1).unwrap_or(&null).clone()
-        } else {
-            null
+        match self.call_ipc_server_api("fetchBlockchainInfo", query_name, args)? {
+            Some(result) => {
+                // Check that the call succeeded.
+                let null = Value::Null;
+                let succeeded = result.get(0).unwrap_or(&null).as_bool().unwrap_or_default();
+                // Check that there is a result of a given type.
+                let value = if succeeded {
+                    result.get(1).unwrap_or(&null).clone()
+                } else {
+                    null
+                };
+                self.cache
+                    .blockchain_info
+                    .borrow_mut()
+                    .insert(cache_key, value.clone());
+                Ok(value)
+            }
+            // Not cached, so that the next query asks the node again.
+            None => Ok(Value::Null),
         }
     }
 
     fn query_jsonrpc_u256(&self, query_name: &str) -> U256 {
         self.query_jsonrpc(query_name, None)
+            .unwrap_or_else(|e| e.abort())
             .as_str()
             .and_then(|s| {
                 let s = s.replace("\"", "");
@@ -132,11 +405,35 @@ impl ScillaBackend {
         query_name: &str,
         key: Option<H256>,
         use_default: bool,
-    ) -> Result<Option<ScillaMessage::ProtoScillaVal>> {
-        info!(
-            "query_state_value: {} {} {:?} {}",
-            address, query_name, key, use_default
-        );
+    ) -> Result<Option<ScillaMessage::ProtoScillaVal>, EvmError> {
+        let cache_key = (address, query_name.to_string(), key, use_default);
+        if let Some(value) = self.cache.state.borrow().get(&cache_key) {
+            self.stats.count_cache_lookup(true);
+            return Ok(value.clone());
+        }
+        self.stats.count_cache_lookup(false);
+        match self.fetch_state_value(address, query_name, key)? {
+            Some(result) => {
+                let value = parse_state_value(&result, address, query_name, use_default)?;
+                self.cache
+                    .state
+                    .borrow_mut()
+                    .insert(cache_key, value.clone());
+                Ok(value)
+            }
+            // Not cached, so that the next query asks the node again.
+            None => Ok(None),
+        }
+    }
+
+    // The answer of the node to a state query, `None` if it answered with an error.
+    fn fetch_state_value(
+        &self,
+        address: H160,
+        query_name: &str,
+        key: Option<H256>,
+    ) -> Result<Option<Value>, EvmError> {
+        info!("query_state_value: {} {} {:?}", address, query_name, key);
         let mut query = ScillaMessage::ProtoScillaQuery::new();
         query.set_name(query_name.into());
         if let Some(key) = key {
@@ -153,79 +450,111 @@ impl ScillaBackend {
             base64::encode(query.write_to_bytes().unwrap()).into(),
         );
 
-        // If the RPC call failed, something is wrong, and it is better to crash.
-        let result = self.call_ipc_server_api("fetchExternalStateValueB64", args);
-        // If the RPC was okay, but we didn't get a value, that's
-        // normal, just return empty code.
-        match result {
-            Ok(result) => {
-                let default_false = Value::Bool(false);
-                if !result
-                    .get(0)
-                    .map_or_else(
-                        || {
-                            if use_default {
-                                Ok(&default_false)
-                            } else {
-                                Err(Error::internal_error())
-                            }
-                        },
-                        Ok,
-                    )?
-                    .as_bool()
-                    .unwrap_or_default()
-                {
-                    return Ok(None);
+        // If we could not talk to the node, the execution cannot go on.
+        self.call_ipc_server_api("fetchExternalStateValueB64", query_name, args)
+    }
+}
+
+// The value in the answer of the node to a state query. If the RPC was okay, but we
+// didn't get a value, that's normal, just return empty code.
+fn parse_state_value(
+    result: &Value,
+    address: H160,
+    query_name: &str,
+    use_default: bool,
+) -> Result<Option<ScillaMessage::ProtoScillaVal>, EvmError> {
+    let malformed = || EvmError::MalformedState(format!("{} of {:?}", query_name, address));
+    let default_false = Value::Bool(false);
+    if !result
+        .get(0)
+        .map_or_else(
+            || {
+                if use_default {
+                    Ok(&default_false)
+                } else {
+                    Err(malformed())
                 }
+            },
+            Ok,
+        )?
+        .as_bool()
+        .unwrap_or_default()
+    {
+        return Ok(None);
+    }
 
-                // Check that there is a result of a given type.
-                let default_value = ScillaMessage::ProtoScillaVal::new();
-                result.get(1).map_or_else(
-                    || {
-                        if use_default {
-                            Ok(Some(default_value))
-                        } else {
-                            Err(Error::internal_error())
-                        }
-                    },
-                    |value| {
-                        value
-                            .as_str()
-                            .map(|value_str| {
-                                base64::decode(value_str).ok().and_then(|buffer| {
-                                    ScillaMessage::ProtoScillaVal::parse_from_bytes(&buffer).ok()
-                                })
-                            })
-                            .ok_or(Error::internal_error())
-                    },
-                )
+    // Check that there is a result of a given type.
+    let default_value = ScillaMessage::ProtoScillaVal::new();
+    result.get(1).map_or_else(
+        || {
+            if use_default {
+                Ok(Some(default_value))
+            } else {
+                Err(malformed())
             }
-            Err(_) => Ok(None),
-        }
-    }
+        },
+        |value| {
+            value
+                .as_str()
+                .map(|value_str| {
+                    base64::decode(value_str).ok().and_then(|buffer| {
+                        ScillaMessage::ProtoScillaVal::parse_from_bytes(&buffer).ok()
+                    })
+                })
+                .ok_or_else(malformed)
+        },
+    )
+}
 
-    // Encode key/value pairs for storage in such a way that the Zilliqa node
-    // could interpret it without much modification.
-    pub(crate) fn encode_storage(&self, key: H256, value: H256) -> (String, String) {
-        let mut query = ScillaMessage::ProtoScillaQuery::new();
-        query.set_name("_evm_storage".into());
-        query.set_indices(vec![bytes::Bytes::from(format!("{:X}", key))]);
-        query.set_mapdepth(1);
-        let mut val = ScillaMessage::ProtoScillaVal::new();
-        let bval = value.as_bytes().to_vec();
-        val.set_bval(bval.into());
-        (
-            base64::encode(query.write_to_bytes().unwrap()),
-            base64::encode(val.write_to_bytes().unwrap()),
-        )
+fn replay_key(method: &str, args: &serde_json::Map<String, Value>) -> (String, String) {
+    (method.to_string(), Value::Object(args.clone()).to_string())
+}
+
+// Encode key/value pairs for storage in such a way that the Zilliqa node
+// could interpret it without much modification.
+pub(crate) fn encode_storage(key: H256, value: H256) -> (String, String) {
+    let mut query = ScillaMessage::ProtoScillaQuery::new();
+    query.set_name("_evm_storage".into());
+    query.set_indices(vec![bytes::Bytes::from(format!("{:X}", key))]);
+    query.set_mapdepth(1);
+    let mut val = ScillaMessage::ProtoScillaVal::new();
+    let bval = value.as_bytes().to_vec();
+    val.set_bval(bval.into());
+    (
+        base64::encode(query.write_to_bytes().unwrap()),
+        base64::encode(val.write_to_bytes().unwrap()),
+    )
+}
+
+impl ZilliqaBackend for ScillaBackend {
+    fn zil_scaling_factor(&self) -> u64 {
+        self.config.zil_scaling_factor
     }
 
-    pub(crate) fn scale_eth_to_zil(&self, eth: U256) -> U256 {
-        eth / self.config.zil_scaling_factor
+    fn precision_policy(&self) -> PrecisionPolicy {
+        self.config.precision_policy
     }
+}
 
-    pub(crate) fn scale_zil_to_eth(&self, zil: U256) -> U256 {
-        zil * self.config.zil_scaling_factor
+impl Drop for ScillaBackend {
+    fn drop(&mut self) {
+        if self.stats.calls.get() > 0 {
+            info!(
+                "Node IPC: {} calls over {} connections in {:?}, cache: {} hits, {} misses",
+                self.stats.calls.get(),
+                self.stats.connections.get(),
+                self.stats.time.get(),
+                self.stats.cache_hits.get(),
+                self.stats.cache_misses.get(),
+            );
+        }
+        // A connection is left consistent by queries that complete, and dropped by those
+        // that time out. Still, a panicking thread keeps none of its resources.
+        if let Some(connection) = self.connection.get_mut().take() {
+            if !std::thread::panicking() {
+                self.config.connections.give_back(connection);
+            }
+        }
     }
 }
 
@@ -235,13 +564,23 @@ impl<'config> Backend for ScillaBackend {
     }
 
     fn origin(&self) -> H160 {
-        let result = self.query_jsonrpc("ORIGIN", None);
-        H160::from_str(result.as_str().expect("origin")).expect("origin hex")
+        let result = self
+            .query_jsonrpc("ORIGIN", None)
+            .unwrap_or_else(|e| e.abort());
+        result
+            .as_str()
+            .and_then(|s| H160::from_str(s).ok())
+            .unwrap_or_else(|| EvmError::MalformedState(format!("origin {}", result)).abort())
     }
 
     fn block_hash(&self, number: U256) -> H256 {
-        let result = self.query_jsonrpc("BLOCKHASH", Some(&number.to_string()));
-        H256::from_str(result.as_str().expect("blockhash")).expect("blockhash hex")
+        let result = self
+            .query_jsonrpc("BLOCKHASH", Some(&number.to_string()))
+            .unwrap_or_else(|e| e.abort());
+        result
+            .as_str()
+            .and_then(|s| H256::from_str(s).ok())
+            .unwrap_or_else(|| EvmError::MalformedState(format!("block hash {}", result)).abort())
     }
 
     fn block_number(&self) -> U256 {
@@ -278,28 +617,31 @@ impl<'config> Backend for ScillaBackend {
     fn exists(&self, address: H160) -> bool {
         // Try to query account balance, and see if it returns Some result.
         self.query_state_value(address, "_balance", None, true)
-            .expect("query_state_value _balance")
+            .unwrap_or_else(|e| e.abort())
             .is_some()
     }
 
     fn basic(&self, address: H160) -> Basic {
         let balance = self
             .query_state_value(address, "_balance", None, true)
-            .expect("query_state_value _balance")
+            .unwrap_or_else(|e| e.abort())
             .and_then(|x| x.as_uint256())
             .unwrap_or_default();
         let nonce = self
             .query_state_value(address, "_nonce", None, true)
-            .expect("query_state_value _nonce")
+            .unwrap_or_else(|e| e.abort())
             .and_then(|x| x.as_uint256())
             .unwrap_or_default();
-        Basic { balance: balance * self.config.zil_scaling_factor, nonce }
+        Basic {
+            balance: balance * self.config.zil_scaling_factor,
+            nonce,
+        }
     }
 
     fn code(&self, address: H160) -> Vec<u8> {
         let bytes = self
             .query_state_value(address, "_code", None, true)
-            .expect("query_state_value(_code)")
+            .unwrap_or_else(|e| e.abort())
             .map(|value| value.as_bytes())
             .unwrap_or_default();
         (if bytes.len() > 2 && bytes[0] == b'E' && bytes[1] == b'V' && bytes[2] == b'M' {
@@ -307,13 +649,15 @@ impl<'config> Backend for ScillaBackend {
         } else {
             hex::decode(bytes)
         })
-        .expect("Code cannot be HEX decoded")
+        .unwrap_or_else(|e| {
+            EvmError::MalformedState(format!("code of {:?}: {}", address, e)).abort()
+        })
     }
 
     fn storage(&self, address: H160, key: H256) -> H256 {
         let mut result = self
             .query_state_value(address, "_evm_storage", Some(key), true)
-            .expect("query_state_value(_evm_storage)")
+            .unwrap_or_else(|e| e.abort())
             .map(|value| value.as_bytes())
             .unwrap_or_default();
         // H256::from_slice expects big-endian, we filled the first bytes from decoding,
@@ -328,3 +672,235 @@ impl<'config> Backend for ScillaBackend {
         Some(self.storage(address, key))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+    use crate::mocknode::{Behaviour, MockNode, MockState};
+
+    fn new_backend(zil_scaling_factor: u64) -> ScillaBackend {
+        // Never connects, as no state is queried.
+        ScillaBackend::new(ScillaBackendConfig {
+            path: PathBuf::new(),
+            zil_scaling_factor,
+            precision_policy: PrecisionPolicy::Dust,
+            connections: NodePool::default(),
+        })
+    }
+
+    #[test]
+    fn split_eth_to_zil() {
+        let backend = new_backend(1_000_000);
+        assert_eq!(
+            backend.split_eth_to_zil(U256::from(3_000_042)),
+            (U256::from(3), U256::from(42))
+        );
+        assert_eq!(
+            backend.split_eth_to_zil(U256::from(999_999)),
+            (U256::zero(), U256::from(999_999))
+        );
+        assert_eq!(
+            new_backend(1).split_eth_to_zil(U256::from(42)),
+            (U256::from(42), U256::zero())
+        );
+    }
+
+    fn connect(node: &MockNode) -> ScillaBackend {
+        ScillaBackend::new(ScillaBackendConfig {
+            path: node.path().to_path_buf(),
+            zil_scaling_factor: 1_000_000,
+            precision_policy: PrecisionPolicy::Dust,
+            connections: NodePool::default(),
+        })
+    }
+
+    // The error aborting `f`, which must abort.
+    fn abort_error<T>(f: impl FnOnce() -> T) -> EvmError {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(_) => panic!("not aborted"),
+            Err(panic) => EvmError::from_panic(panic),
+        }
+    }
+
+    #[test]
+    fn reads_state_from_the_node() {
+        let (account, empty) = (H160::repeat_byte(1), H160::repeat_byte(2));
+        let key = H256::from_low_u64_be(1);
+        let node = MockNode::start(
+            MockState::default()
+                .balance(account, U256::from(10))
+                .nonce(account, U256::from(2))
+                .code(account, &[0x60, 0x00])
+                .storage(account, key, H256::from_low_u64_be(42))
+                .blockchain_info("BLOCKNUMBER", "", "42")
+                .blockchain_info("CHAINID", "", "1")
+                .blockchain_info("BLOCKHASH", "41", &format!("{:?}", H256::repeat_byte(7))),
+        );
+        let backend = connect(&node);
+        assert!(backend.exists(account));
+        assert_eq!(backend.basic(account).balance, U256::from(10_000_000));
+        assert_eq!(backend.basic(account).nonce, U256::from(2));
+        assert_eq!(backend.code(account), vec![0x60, 0x00]);
+        assert_eq!(backend.storage(account, key), H256::from_low_u64_be(42));
+        assert_eq!(backend.storage(account, H256::zero()), H256::zero());
+        assert!(!backend.exists(empty));
+        assert_eq!(backend.basic(empty), Basic::default());
+        assert!(backend.code(empty).is_empty());
+        assert_eq!(backend.block_number(), U256::from(42));
+        assert_eq!(backend.chain_id(), U256::from(BASE_CHAIN_ID + 1));
+        assert_eq!(backend.block_hash(U256::from(41)), H256::repeat_byte(7));
+        // Missing info reads as zero.
+        assert_eq!(backend.block_timestamp(), U256::zero());
+
+        // Answers are cached for the lifetime of the backend.
+        let calls = node.calls();
+        backend.basic(account);
+        backend.block_number();
+        assert_eq!(node.calls(), calls);
+        assert_eq!(backend.stats.connections.get(), 1);
+    }
+
+    #[test]
+    fn node_errors() {
+        let account = H160::repeat_byte(1);
+        let node = MockNode::start(MockState::default().balance(account, U256::from(10)));
+
+        // Queries that fail are read as absent values.
+        node.set_behaviour(Behaviour::Failing);
+        let backend = connect(&node);
+        assert!(!backend.exists(account));
+        assert_eq!(backend.block_number(), U256::zero());
+        // And not cached, so that the node is asked again.
+        node.set_behaviour(Behaviour::Normal);
+        assert!(backend.exists(account));
+        assert_eq!(backend.basic(account).balance, U256::from(10_000_000));
+
+        node.set_behaviour(Behaviour::Fixed(Value::from(vec![
+            Value::Bool(true),
+            Value::from(42),
+        ])));
+        let backend = connect(&node);
+        assert!(matches!(
+            abort_error(|| backend.basic(account)),
+            EvmError::MalformedState(_)
+        ));
+        assert!(matches!(
+            abort_error(|| backend.origin()),
+            EvmError::MalformedState(_)
+        ));
+
+        let mut code = ScillaMessage::ProtoScillaVal::new();
+        code.set_bval(b"EVMnot hex".to_vec().into());
+        node.set_behaviour(Behaviour::Fixed(Value::from(vec![
+            Value::Bool(true),
+            Value::from(base64::encode(code.write_to_bytes().unwrap())),
+        ])));
+        let backend = connect(&node);
+        assert!(matches!(
+            abort_error(|| backend.code(account)),
+            EvmError::MalformedState(_)
+        ));
+
+        node.set_behaviour(Behaviour::Delayed(NODE_TIMEOUT + Duration::from_secs(1)));
+        let backend = connect(&node);
+        assert!(matches!(
+            abort_error(|| backend.basic(account)),
+            EvmError::Timeout(_)
+        ));
+
+        let path = node.path().to_path_buf();
+        drop(node);
+        let backend = ScillaBackend::new(ScillaBackendConfig {
+            path,
+            zil_scaling_factor: 1_000_000,
+            precision_policy: PrecisionPolicy::Dust,
+            connections: NodePool::default(),
+        });
+        assert!(matches!(
+            abort_error(|| backend.basic(account)),
+            EvmError::NodeUnreachable(_)
+        ));
+    }
+
+    #[test]
+    fn connections_are_kept_across_backends() {
+        let account = H160::repeat_byte(1);
+        let node = MockNode::start(MockState::default().balance(account, U256::from(10)));
+        let config = ScillaBackendConfig {
+            path: node.path().to_path_buf(),
+            zil_scaling_factor: 1_000_000,
+            precision_policy: PrecisionPolicy::Dust,
+            connections: NodePool::default(),
+        };
+        for _ in 0..2 {
+            let backend = ScillaBackend::new(config.clone());
+            assert!(backend.exists(account));
+        }
+        assert_eq!(node.connections(), 1);
+
+        // A connection whose query timed out is not kept, as it may still get the late answer.
+        node.set_behaviour(Behaviour::Delayed(NODE_TIMEOUT + Duration::from_secs(1)));
+        let backend = ScillaBackend::new(config.clone());
+        assert!(matches!(
+            abort_error(|| backend.basic(account)),
+            EvmError::Timeout(_)
+        ));
+        drop(backend);
+        node.set_behaviour(Behaviour::Normal);
+        let backend = ScillaBackend::new(config.clone());
+        assert!(backend.exists(account));
+        assert_eq!(node.connections(), 2);
+
+        // Nor is the connection of a panicking thread kept.
+        abort_error(move || {
+            let _backend = backend;
+            EvmError::EvmFatal("in an execution".to_string()).abort()
+        });
+        let backend = ScillaBackend::new(config.clone());
+        assert!(backend.exists(account));
+        assert_eq!(node.connections(), 3);
+        drop(backend);
+
+        // Connections the node closed are replaced.
+        let path = node.path().to_path_buf();
+        drop(node);
+        let node = MockNode::start_at(&path, MockState::default().balance(account, U256::from(10)));
+        let backend = ScillaBackend::new(config);
+        assert!(backend.exists(account));
+        assert_eq!(node.connections(), 1);
+    }
+
+    #[test]
+    fn replays_recorded_queries() {
+        let account = H160::repeat_byte(1);
+        let node = MockNode::start(
+            MockState::default()
+                .balance(account, U256::from(10))
+                .blockchain_info("BLOCKNUMBER", "", "42"),
+        );
+        let queries = Arc::new(Mutex::new(vec![]));
+        let backend = connect(&node).with_recording(queries.clone());
+        let basic = backend.basic(account);
+        let block_number = backend.block_number();
+        drop(backend);
+        let config = ScillaBackendConfig {
+            path: node.path().to_path_buf(),
+            zil_scaling_factor: 1_000_000,
+            precision_policy: PrecisionPolicy::Dust,
+            connections: NodePool::default(),
+        };
+        drop(node);
+
+        let queries = queries.lock().unwrap().clone();
+        assert_eq!(queries.len(), 3);
+        let backend = ScillaBackend::replaying(config, queries);
+        assert_eq!(backend.basic(account), basic);
+        assert_eq!(backend.block_number(), block_number);
+        assert!(matches!(
+            abort_error(|| backend.code(account)),
+            EvmError::NodeUnreachable(_)
+        ));
+    }
+}
@@ -0,0 +1,408 @@
+/// Conformance with Ethereum: runs the GeneralStateTests of ethereum/tests through
+/// `run_evm_impl` and `create_impl`, with all scaling factors set to 1, and compares the
+/// post-state roots and the hashes of the logs with the expected ones.
+///
+/// A pinned subset of the fixtures is fetched into `tests/GeneralStateTests` by
+/// `tests/tools/fetch_state_tests.sh`, and run with
+/// `cargo test pinned_state_tests -- --ignored --nocapture`. The others are run with
+/// `STATE_TESTS` set to a directory or a file of fixtures, and
+/// `cargo test general_state_tests -- --ignored --nocapture`.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use evm::backend::Apply;
+use jsonrpc_core::serde_json;
+use primitive_types::{H160, H256, U256};
+use sha3::{Digest, Keccak256};
+
+use crate::access_list::AccessListItem;
+use crate::forks::{Fork, ForkSchedule};
+use crate::gas::{GasRounding, GasScaling};
+use crate::memorybackend::{self, InMemoryBackend, MemoryAccount, MemoryEnv, MemoryState};
+use crate::scillabackend::PrecisionPolicy;
+use crate::{create_impl, run_evm_impl, RunParams};
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StateTest {
+    env: TestEnv,
+    pre: BTreeMap<H160, TestAccount>,
+    transaction: TestTransaction,
+    post: BTreeMap<String, Vec<PostState>>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TestEnv {
+    current_coinbase: H160,
+    current_difficulty: U256,
+    current_gas_limit: U256,
+    current_number: U256,
+    current_timestamp: U256,
+    current_base_fee: Option<U256>,
+    previous_hash: H256,
+}
+
+#[derive(serde::Deserialize)]
+struct TestAccount {
+    balance: U256,
+    nonce: U256,
+    #[serde(deserialize_with = "memorybackend::from_hex")]
+    code: Vec<u8>,
+    storage: BTreeMap<U256, U256>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TestTransaction {
+    data: Vec<String>,
+    gas_limit: Vec<U256>,
+    value: Vec<U256>,
+    gas_price: Option<U256>,
+    max_fee_per_gas: Option<U256>,
+    max_priority_fee_per_gas: Option<U256>,
+    secret_key: H256,
+    // Empty for a creation.
+    to: String,
+    access_lists: Option<Vec<Option<Vec<AccessListItem>>>>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PostState {
+    hash: H256,
+    logs: H256,
+    indexes: Indexes,
+    expect_exception: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct Indexes {
+    data: usize,
+    gas: usize,
+    value: usize,
+}
+
+#[derive(Default)]
+struct ForkReport {
+    passed: usize,
+    failed: Vec<String>,
+    // Transactions that are expected to be invalid, which the node rejects before the EVM.
+    skipped: usize,
+}
+
+#[test]
+#[ignore]
+fn pinned_state_tests() {
+    run_state_tests(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/GeneralStateTests"));
+}
+
+#[test]
+#[ignore]
+fn general_state_tests() {
+    let root = std::env::var_os("STATE_TESTS")
+        .expect("STATE_TESTS must be set to fixtures, see tests/tools/fetch_state_tests.sh");
+    run_state_tests(Path::new(&root));
+}
+
+/// Runs the fixtures in `root`, prints a report per fork and fails if any test failed.
+fn run_state_tests(root: &Path) {
+    let mut files = vec![];
+    collect_fixtures(root, &mut files);
+    assert!(
+        !files.is_empty(),
+        "no fixtures in {}, see tests/tools/fetch_state_tests.sh",
+        root.display()
+    );
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut reports: BTreeMap<String, ForkReport> = BTreeMap::new();
+    for file in files {
+        let json = std::fs::read_to_string(&file).unwrap();
+        let tests: BTreeMap<String, StateTest> =
+            serde_json::from_str(&json).unwrap_or_else(|e| panic!("{}: {}", file.display(), e));
+        for (name, test) in tests {
+            for (fork_name, posts) in &test.post {
+                // Only the forks that evm-ds implements.
+                let fork: Fork = match fork_name.parse() {
+                    Ok(fork) => fork,
+                    Err(_) => continue,
+                };
+                let report = reports.entry(fork_name.clone()).or_default();
+                for post in posts {
+                    if post.expect_exception.is_some() {
+                        report.skipped += 1;
+                        continue;
+                    }
+                    let result = runtime.block_on(run_test(&test, fork, fork_name, post));
+                    match result {
+                        Ok(()) => report.passed += 1,
+                        Err(e) => {
+                            let indexes = &post.indexes;
+                            report.failed.push(format!(
+                                "{} {} [d{} g{} v{}]: {}",
+                                file.display(),
+                                name,
+                                indexes.data,
+                                indexes.gas,
+                                indexes.value,
+                                e
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut failed = 0;
+    for (fork, report) in &reports {
+        for failure in &report.failed {
+            println!("FAIL {} {}", fork, failure);
+        }
+        failed += report.failed.len();
+    }
+    println!(
+        "{:<12} {:>8} {:>8} {:>8}",
+        "fork", "passed", "failed", "skipped"
+    );
+    for (fork, report) in &reports {
+        println!(
+            "{:<12} {:>8} {:>8} {:>8}",
+            fork,
+            report.passed,
+            report.failed.len(),
+            report.skipped
+        );
+    }
+    assert_eq!(failed, 0, "state tests failed");
+}
+
+fn collect_fixtures(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        let mut entries: Vec<_> = std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        entries.sort();
+        for entry in entries {
+            collect_fixtures(&entry, files);
+        }
+    } else if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        files.push(path.to_path_buf());
+    }
+}
+
+/// Executes the transaction of `post` and checks the state and the logs it leads to.
+/// The node charges the fees and pays the miner, outside the EVM, so this is done here.
+async fn run_test(
+    test: &StateTest,
+    fork: Fork,
+    fork_name: &str,
+    post: &PostState,
+) -> Result<(), String> {
+    let tx = &test.transaction;
+    let config = fork.config();
+    let sender = sender_address(&tx.secret_key)?;
+    let gas_limit = tx.gas_limit[post.indexes.gas];
+    if gas_limit > U256::from(u64::MAX) {
+        return Err(format!("gas limit {} does not fit in 64 bits", gas_limit));
+    }
+    let gas_limit = gas_limit.as_u64();
+    let base_fee = test.env.current_base_fee.unwrap_or_default();
+    let (gas_price, miner_price) = match (tx.max_fee_per_gas, tx.max_priority_fee_per_gas) {
+        (Some(max_fee), Some(max_priority_fee)) => {
+            let price = max_fee.min(base_fee + max_priority_fee);
+            (price, price.saturating_sub(base_fee))
+        }
+        _ => {
+            let price = tx.gas_price.unwrap_or_default();
+            let miner_price = if fork == Fork::London {
+                price.saturating_sub(base_fee)
+            } else {
+                price
+            };
+            (price, miner_price)
+        }
+    };
+
+    let mut state = pre_state(test, gas_price, sender);
+    let sender_account = state.accounts.entry(sender).or_default();
+    let upfront = U256::from(gas_limit) * gas_price;
+    sender_account.balance = sender_account
+        .balance
+        .checked_sub(upfront)
+        .ok_or("the sender cannot pay for the gas")?;
+
+    let mut backend = InMemoryBackend::new(state, 1, PrecisionPolicy::Truncate);
+    let gas_scaling = GasScaling::new(1, GasRounding::Floor);
+    let fork_schedule = ForkSchedule::new(fork);
+    let data = tx.data[post.indexes.data]
+        .trim_start_matches("0x")
+        .to_string();
+    let value = tx.value[post.indexes.value].to_string();
+    let (result, state_apply) = if tx.to.is_empty() {
+        create_impl(
+            format!("{:?}", sender),
+            data,
+            value,
+            gas_limit,
+            None,
+            backend.clone(),
+            false,
+            gas_scaling,
+            Some(fork_name.to_string()),
+            fork_schedule,
+        )
+        .await
+        .map_err(|e| e.message)?
+    } else {
+        let params = RunParams {
+            address: tx.to.clone(),
+            caller: format!("{:?}", sender),
+            data,
+            apparent_value: value,
+            gas_limit,
+            fork: Some(fork_name.to_string()),
+            mode: Some("transaction".to_string()),
+            access_list: tx
+                .access_lists
+                .as_ref()
+                .and_then(|lists| lists[post.indexes.data].clone()),
+            ..Default::default()
+        };
+        let (result, details) =
+            run_evm_impl(params, backend.clone(), false, gas_scaling, fork_schedule)
+                .await
+                .map_err(|e| e.message)?;
+        (result, details.state_apply)
+    };
+    if let Some(error) = &result.error {
+        return Err(error.to_string());
+    }
+
+    let mut touched: Vec<H160> = state_apply
+        .iter()
+        .map(|apply| match apply {
+            Apply::Modify { address, .. } | Apply::Delete { address } => *address,
+        })
+        .collect();
+    backend.apply(state_apply);
+    let mut state = backend.state().clone();
+    let refund = U256::from(result.remaining_gas) * gas_price;
+    state.accounts.entry(sender).or_default().balance += refund;
+    let reward = U256::from(result.evm_gas_used) * miner_price;
+    state
+        .accounts
+        .entry(test.env.current_coinbase)
+        .or_default()
+        .balance += reward;
+    touched.push(test.env.current_coinbase);
+    // EIP-161: touched accounts that end up empty are deleted.
+    if !config.empty_considered_exists {
+        for address in touched {
+            if state.accounts.get(&address).is_some_and(is_empty) {
+                state.accounts.remove(&address);
+            }
+        }
+    }
+
+    let logs = rlp::encode_list::<ethereum::Log, _>(&result.logs);
+    let logs_hash = H256::from_slice(&Keccak256::digest(&logs));
+    if logs_hash != post.logs {
+        return Err(format!(
+            "logs hash {:?}, expected {:?}",
+            logs_hash, post.logs
+        ));
+    }
+    let root = state_root(&state.accounts);
+    if root != post.hash {
+        return Err(format!(
+            "state root {:?}, expected {:?} ({:?})",
+            root, post.hash, result.exit_reason
+        ));
+    }
+    Ok(())
+}
+
+fn pre_state(test: &StateTest, gas_price: U256, sender: H160) -> MemoryState {
+    let env = &test.env;
+    let mut block_hashes = BTreeMap::new();
+    if !env.current_number.is_zero() {
+        block_hashes.insert(env.current_number - 1, env.previous_hash);
+    }
+    MemoryState {
+        env: MemoryEnv {
+            gas_price,
+            origin: sender,
+            chain_id: U256::one(),
+            block_number: env.current_number,
+            block_coinbase: env.current_coinbase,
+            block_timestamp: env.current_timestamp,
+            block_difficulty: env.current_difficulty,
+            block_gas_limit: env.current_gas_limit,
+            block_base_fee_per_gas: env.current_base_fee.unwrap_or_default(),
+            block_hashes,
+        },
+        accounts: test
+            .pre
+            .iter()
+            .map(|(address, account)| {
+                let storage = account
+                    .storage
+                    .iter()
+                    .filter(|(_, value)| !value.is_zero())
+                    .map(|(key, value)| (u256_to_h256(*key), u256_to_h256(*value)))
+                    .collect();
+                let account = MemoryAccount {
+                    balance: account.balance,
+                    nonce: account.nonce,
+                    code: account.code.clone(),
+                    storage,
+                };
+                (*address, account)
+            })
+            .collect(),
+    }
+}
+
+fn sender_address(secret_key: &H256) -> Result<H160, String> {
+    let secret_key = libsecp256k1::SecretKey::parse_slice(secret_key.as_bytes())
+        .map_err(|e| format!("secret key: {:?}", e))?;
+    let public_key = libsecp256k1::PublicKey::from_secret_key(&secret_key);
+    let hash = Keccak256::digest(&public_key.serialize()[1..]);
+    Ok(H160::from_slice(&hash[12..]))
+}
+
+fn is_empty(account: &MemoryAccount) -> bool {
+    account.balance.is_zero() && account.nonce.is_zero() && account.code.is_empty()
+}
+
+fn u256_to_h256(value: U256) -> H256 {
+    let mut hash = H256::zero();
+    value.to_big_endian(hash.as_bytes_mut());
+    hash
+}
+
+/// Root of the secure trie of the accounts, as in the block headers of Ethereum.
+fn state_root(accounts: &BTreeMap<H160, MemoryAccount>) -> H256 {
+    let accounts =
+        accounts.iter().map(|(address, account)| {
+            let storage_root =
+                ethereum::util::sec_trie_root(account.storage.iter().map(|(key, value)| {
+                    (*key, rlp::encode(&U256::from_big_endian(value.as_bytes())))
+                }));
+            let code_hash = H256::from_slice(&Keccak256::digest(&account.code));
+            let mut stream = rlp::RlpStream::new_list(4);
+            stream
+                .append(&account.nonce)
+                .append(&account.balance)
+                .append(&storage_root)
+                .append(&code_hash);
+            (*address, stream.out())
+        });
+    ethereum::util::sec_trie_root(accounts)
+}
@@ -0,0 +1,340 @@
+/// Call tracer producing a tree of frames like geth's `callTracer`, to find where in a chain
+/// of nested calls an execution failed.
+use evm::{CreateScheme, ExitReason};
+use evm_gasometer::tracing::{Event as GasometerEvent, Snapshot};
+use jsonrpc_core::{serde_json, Value};
+use primitive_types::{H160, U256};
+
+use super::{TraceOutcome, Tracer};
+use crate::revert::decode_revert_reason;
+
+// Gas added to what a CALL or CALLCODE transferring value gives to the callee.
+const CALL_STIPEND: u64 = 2300;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CallFrame {
+    #[serde(rename = "type")]
+    call_type: &'static str,
+    from: H160,
+    to: H160,
+    /// In Eth units, absent for calls that do not transfer value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<U256>,
+    /// In EVM gas units, as all gas of the trace.
+    gas: U256,
+    gas_used: U256,
+    input: String,
+    output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revert_reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    calls: Vec<CallFrame>,
+    // Whether the gas given to the frame is known, from the first gasometer event after
+    // the frame was entered.
+    #[serde(skip)]
+    gas_given: bool,
+    // Gas the frame gets on top of what its caller records for it.
+    #[serde(skip)]
+    stipend: u64,
+    #[serde(skip)]
+    is_static: bool,
+}
+
+impl CallFrame {
+    fn new(call_type: &'static str, from: H160, to: H160, value: Option<U256>) -> Self {
+        Self {
+            call_type,
+            from,
+            to,
+            value,
+            gas: U256::zero(),
+            gas_used: U256::zero(),
+            input: "0x".to_string(),
+            output: "0x".to_string(),
+            error: None,
+            revert_reason: None,
+            calls: Vec::new(),
+            gas_given: false,
+            stipend: 0,
+            is_static: false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CallTracer {
+    // Frames entered and not exited yet, the innermost last.
+    stack: Vec<CallFrame>,
+    root: Option<CallFrame>,
+}
+
+impl CallTracer {
+    fn enter(&mut self, frame: CallFrame) {
+        self.stack.push(frame);
+    }
+
+    fn exit(&mut self, reason: &ExitReason, return_value: &[u8]) {
+        let mut frame = match self.stack.pop() {
+            Some(frame) => frame,
+            // Transactions failing their checks exit without entering.
+            None => return,
+        };
+        frame.output = format!("0x{}", hex::encode(return_value));
+        match reason {
+            ExitReason::Succeed(_) => {}
+            ExitReason::Revert(_) => {
+                frame.error = Some("execution reverted".to_string());
+                frame.revert_reason = decode_revert_reason(return_value);
+            }
+            reason => frame.error = Some(format!("{:?}", reason)),
+        }
+        match self.stack.last_mut() {
+            Some(parent) => parent.calls.push(frame),
+            None => self.root = Some(frame),
+        }
+    }
+
+    // Keeps the gas of the current frame up to date with its gasometer. The first event
+    // after a nested frame is entered is its caller recording the gas it gives to it.
+    fn record_gas(&mut self, snapshot: Option<Snapshot>, cost: i128) {
+        let snapshot = match snapshot {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        let depth = self.stack.len();
+        let frame = match self.stack.last_mut() {
+            Some(frame) => frame,
+            None => return,
+        };
+        if !frame.gas_given {
+            frame.gas_given = true;
+            if depth > 1 {
+                frame.gas = U256::from(cost.max(0) as u128) + frame.stipend;
+                self.stack[depth - 2].gas_used = used_gas(&snapshot, cost);
+                return;
+            }
+            // The outermost frame has the whole gas limit of the execution.
+            frame.gas = snapshot.gas_limit.into();
+        }
+        frame.gas_used = used_gas(&snapshot, cost);
+    }
+
+    // The gas left by the current frame when it exits is returned to its caller, before
+    // the frame exits.
+    fn return_gas(&mut self, snapshot: Option<Snapshot>, stipend: u64) {
+        let depth = self.stack.len();
+        if depth < 2 {
+            return;
+        }
+        if let Some(snapshot) = snapshot {
+            self.stack[depth - 2].gas_used = used_gas(&snapshot, -i128::from(stipend));
+        }
+    }
+}
+
+// Gas used by a gasometer once `cost` is recorded.
+fn used_gas(snapshot: &Snapshot, cost: i128) -> U256 {
+    let used = i128::from(snapshot.used_gas) + i128::from(snapshot.memory_gas) + cost;
+    U256::from(used.max(0) as u128)
+}
+
+impl Tracer for CallTracer {
+    fn evm_event(&mut self, event: evm::tracing::Event) {
+        match event {
+            evm::tracing::Event::Call {
+                code_address,
+                transfer,
+                input,
+                target_gas,
+                is_static,
+                context,
+            } => {
+                let parent_is_static = self.stack.last().is_some_and(|frame| frame.is_static);
+                let mut frame = if code_address != context.address {
+                    // Runs the code of `code_address` as the current contract.
+                    let call_type = match transfer {
+                        Some(_) => "CALLCODE",
+                        None => "DELEGATECALL",
+                    };
+                    let value = transfer.as_ref().map(|transfer| transfer.value);
+                    CallFrame::new(call_type, context.address, code_address, value)
+                } else if is_static && !parent_is_static {
+                    CallFrame::new("STATICCALL", context.caller, code_address, None)
+                } else {
+                    let value = Some(context.apparent_value);
+                    CallFrame::new("CALL", context.caller, code_address, value)
+                };
+                // Only calls made by opcodes get a stipend, not the outermost one.
+                let transfers_value = transfer
+                    .as_ref()
+                    .is_some_and(|transfer| !transfer.value.is_zero());
+                if transfers_value && !self.stack.is_empty() {
+                    frame.stipend = CALL_STIPEND;
+                }
+                frame.input = format!("0x{}", hex::encode(input));
+                frame.gas = target_gas.unwrap_or_default().into();
+                frame.is_static = is_static;
+                self.enter(frame);
+            }
+            evm::tracing::Event::Create {
+                caller,
+                address,
+                scheme,
+                value,
+                init_code,
+                target_gas,
+            } => {
+                let call_type = match scheme {
+                    CreateScheme::Create2 { .. } => "CREATE2",
+                    _ => "CREATE",
+                };
+                let mut frame = CallFrame::new(call_type, caller, address, Some(value));
+                frame.input = format!("0x{}", hex::encode(init_code));
+                frame.gas = target_gas.unwrap_or_default().into();
+                self.enter(frame);
+            }
+            evm::tracing::Event::Suicide {
+                address,
+                target,
+                balance,
+            } => {
+                if let Some(parent) = self.stack.last_mut() {
+                    let frame = CallFrame::new("SELFDESTRUCT", address, target, Some(balance));
+                    parent.calls.push(frame);
+                }
+            }
+            evm::tracing::Event::Exit {
+                reason,
+                return_value,
+            } => self.exit(reason, return_value),
+            _ => {}
+        }
+    }
+
+    fn gasometer_event(&mut self, event: GasometerEvent) {
+        match event {
+            GasometerEvent::RecordCost { cost, snapshot } => self.record_gas(snapshot, cost.into()),
+            GasometerEvent::RecordDynamicCost {
+                gas_cost,
+                memory_gas,
+                snapshot,
+                ..
+            } => {
+                let memory_cost =
+                    snapshot.map_or(0, |snapshot| memory_gas.saturating_sub(snapshot.memory_gas));
+                self.record_gas(snapshot, i128::from(gas_cost) + i128::from(memory_cost))
+            }
+            // Gas returned by a finished subcall.
+            GasometerEvent::RecordStipend { stipend, snapshot } => {
+                self.return_gas(snapshot, stipend)
+            }
+            _ => {}
+        }
+    }
+
+    fn result(&self, outcome: &TraceOutcome) -> Value {
+        self.root
+            .as_ref()
+            .map(|root| {
+                let mut value = serde_json::to_value(root).unwrap_or(Value::Null);
+                // Unlike the gasometer, this accounts for refunds.
+                value["gasUsed"] =
+                    serde_json::to_value(U256::from(outcome.used_gas)).unwrap_or_default();
+                value
+            })
+            .unwrap_or(Value::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::forks::{Fork, ForkSchedule};
+    use crate::gas::{GasRounding, GasScaling};
+    use crate::memorybackend::{InMemoryBackend, MemoryAccount, MemoryState};
+    use crate::scillabackend::PrecisionPolicy;
+    use crate::tracers::TracerConfig;
+    use crate::{run_evm_impl, RunParams};
+
+    fn json(value: impl serde::Serialize) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn nested_call_reverting() {
+        let (caller, address, callee) = (
+            H160::from_str("0x000000000000000000000000000073656e646572").unwrap(),
+            H160::from_str("0x0000000000000000000000007265636569766572").unwrap(),
+            H160::repeat_byte(0xbb),
+        );
+        // Reverts with Error("nope"): 18 pushes, shifts and stores of 3 gas, and 4 words
+        // of memory for 12 gas.
+        let callee_code = concat!(
+            "6308c379a060e01b600052",
+            "6020600452",
+            "6004602452",
+            "636e6f706560e01b604452",
+            "60646000fd",
+        );
+        let mut state = MemoryState::default();
+        state.accounts.insert(
+            callee,
+            MemoryAccount {
+                code: hex::decode(callee_code).unwrap(),
+                ..Default::default()
+            },
+        );
+        let backend = InMemoryBackend::new(state, 1, PrecisionPolicy::Dust);
+        // CALL(10000, callee, 0, 0, 0, 0, 0) STOP
+        let code = format!("6000600060006000600073{}612710f100", hex::encode(callee));
+        let params = RunParams {
+            address: format!("{:?}", address),
+            caller: format!("{:?}", caller),
+            code,
+            apparent_value: "0".to_string(),
+            gas_limit: 100_000,
+            tracer: Some(TracerConfig::CallTracer),
+            ..Default::default()
+        };
+        let (result, _) = run_evm_impl(
+            params,
+            backend,
+            false,
+            GasScaling::new(1, GasRounding::Floor),
+            ForkSchedule::new(Fork::London),
+        )
+        .await
+        .unwrap();
+        assert!(result.exit_reason.is_succeed());
+        let trace = result.trace.unwrap();
+
+        assert_eq!(trace["type"], "CALL");
+        assert_eq!(trace["from"], json(caller));
+        assert_eq!(trace["to"], json(address));
+        assert_eq!(trace["value"], json(U256::zero()));
+        assert_eq!(trace["gas"], json(U256::from(100_000)));
+        // 7 pushes, a cold CALL and what the callee used.
+        assert_eq!(trace["gasUsed"], json(U256::from(21 + 2_600 + 66)));
+        assert_eq!(trace["output"], "0x");
+        assert!(trace.get("error").is_none());
+
+        let calls = trace["calls"].as_array().unwrap();
+        assert_eq!(calls.len(), 1);
+        let inner = &calls[0];
+        assert_eq!(inner["type"], "CALL");
+        assert_eq!(inner["from"], json(address));
+        assert_eq!(inner["to"], json(callee));
+        assert_eq!(inner["value"], json(U256::zero()));
+        assert_eq!(inner["gas"], json(U256::from(10_000)));
+        assert_eq!(inner["gasUsed"], json(U256::from(66)));
+        assert_eq!(inner["error"], "execution reverted");
+        assert_eq!(inner["revertReason"], "nope");
+        assert!(inner["output"].as_str().unwrap().starts_with("0x08c379a0"));
+        assert!(inner.get("calls").is_none());
+    }
+}
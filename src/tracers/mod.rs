@@ -0,0 +1,234 @@
+/// Tracers collecting details of an execution, returned in the `trace` field of `EvmResult`.
+mod call_tracer;
+mod prestate_tracer;
+mod struct_logger;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use evm::backend::{Apply, Backend, Basic};
+use evm::ExitReason;
+use jsonrpc_core::Value;
+use primitive_types::{H160, H256, U256};
+
+use call_tracer::CallTracer;
+use prestate_tracer::PrestateTracer;
+use struct_logger::StructLogger;
+pub use struct_logger::StructLoggerConfig;
+
+/// Receives the events of the EVM, the runtime and the gasometer during an execution.
+pub trait Tracer {
+    fn evm_event(&mut self, _event: evm::tracing::Event) {}
+
+    fn runtime_event(&mut self, _event: evm_runtime::tracing::Event) {}
+
+    fn gasometer_event(&mut self, _event: evm_gasometer::tracing::Event) {}
+
+    fn backend_event(&mut self, _event: BackendEvent) {}
+
+    /// The trace of the finished execution.
+    fn result(&self, outcome: &TraceOutcome) -> Value;
+}
+
+/// State read by the EVM from the backend.
+pub enum BackendEvent<'a> {
+    Basic {
+        address: H160,
+        basic: &'a Basic,
+    },
+    Code {
+        address: H160,
+        code: &'a [u8],
+    },
+    Storage {
+        address: H160,
+        index: H256,
+        value: H256,
+    },
+}
+
+/// What a tracer gets to see of a finished execution.
+pub struct TraceOutcome<'a> {
+    pub exit_reason: &'a ExitReason,
+    pub return_value: &'a [u8],
+    /// In EVM gas units.
+    pub used_gas: u64,
+    /// The state changes, with balances in Eth units.
+    pub apply: &'a [Apply<BTreeMap<H256, H256>>],
+}
+
+/// Each event stream needs its own listener, so they share the tracer.
+pub type SharedTracer = Rc<RefCell<dyn Tracer>>;
+
+/// A tracer requested for an execution, given in requests by its geth name, with the
+/// options of the struct logger next to it: `{"name": "structLogger", "disableStack": true}`.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "name", rename_all = "camelCase")]
+pub enum TracerConfig {
+    StructLogger(StructLoggerConfig),
+    CallTracer,
+    PrestateTracer,
+}
+
+// A tracer by its geth name, with the default options, as given on the command line.
+impl FromStr for TracerConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "structLogger" => Ok(TracerConfig::StructLogger(StructLoggerConfig::default())),
+            "callTracer" => Ok(TracerConfig::CallTracer),
+            "prestateTracer" => Ok(TracerConfig::PrestateTracer),
+            _ => Err(format!("unknown tracer '{}'", s)),
+        }
+    }
+}
+
+impl TracerConfig {
+    pub fn build(self) -> SharedTracer {
+        match self {
+            TracerConfig::StructLogger(config) => Rc::new(RefCell::new(StructLogger::new(config))),
+            TracerConfig::CallTracer => Rc::new(RefCell::new(CallTracer::default())),
+            TracerConfig::PrestateTracer => Rc::new(RefCell::new(PrestateTracer::default())),
+        }
+    }
+}
+
+// Forwards the events of one of the event streams to the tracer.
+struct Listener(SharedTracer);
+
+impl evm::tracing::EventListener for Listener {
+    fn event(&mut self, event: evm::tracing::Event) {
+        self.0.borrow_mut().evm_event(event);
+    }
+}
+
+impl evm_runtime::tracing::EventListener for Listener {
+    fn event(&mut self, event: evm_runtime::tracing::Event) {
+        self.0.borrow_mut().runtime_event(event);
+    }
+}
+
+impl evm_gasometer::tracing::EventListener for Listener {
+    fn event(&mut self, event: evm_gasometer::tracing::Event) {
+        self.0.borrow_mut().gasometer_event(event);
+    }
+}
+
+/// Runs `f` with all events of the EVM, the runtime and the gasometer passed to `tracer`.
+pub fn using<R>(tracer: &SharedTracer, f: impl FnOnce() -> R) -> R {
+    let mut evm_listener = Listener(tracer.clone());
+    let mut runtime_listener = Listener(tracer.clone());
+    let mut gasometer_listener = Listener(tracer.clone());
+    evm::tracing::using(&mut evm_listener, || {
+        evm_runtime::tracing::using(&mut runtime_listener, || {
+            evm_gasometer::tracing::using(&mut gasometer_listener, f)
+        })
+    })
+}
+
+/// Backend passing all state read by the EVM to the tracer, if there is one.
+pub struct TracingBackend<'a, B> {
+    backend: &'a B,
+    tracer: Option<SharedTracer>,
+}
+
+impl<'a, B: Backend> TracingBackend<'a, B> {
+    pub fn new(backend: &'a B, tracer: Option<SharedTracer>) -> Self {
+        Self { backend, tracer }
+    }
+
+    fn report(&self, event: BackendEvent) {
+        if let Some(tracer) = &self.tracer {
+            tracer.borrow_mut().backend_event(event);
+        }
+    }
+}
+
+impl<B: Backend> Backend for TracingBackend<'_, B> {
+    fn gas_price(&self) -> U256 {
+        self.backend.gas_price()
+    }
+
+    fn origin(&self) -> H160 {
+        self.backend.origin()
+    }
+
+    fn block_hash(&self, number: U256) -> H256 {
+        self.backend.block_hash(number)
+    }
+
+    fn block_number(&self) -> U256 {
+        self.backend.block_number()
+    }
+
+    fn block_coinbase(&self) -> H160 {
+        self.backend.block_coinbase()
+    }
+
+    fn block_timestamp(&self) -> U256 {
+        self.backend.block_timestamp()
+    }
+
+    fn block_difficulty(&self) -> U256 {
+        self.backend.block_difficulty()
+    }
+
+    fn block_gas_limit(&self) -> U256 {
+        self.backend.block_gas_limit()
+    }
+
+    fn block_base_fee_per_gas(&self) -> U256 {
+        self.backend.block_base_fee_per_gas()
+    }
+
+    fn chain_id(&self) -> U256 {
+        self.backend.chain_id()
+    }
+
+    fn exists(&self, address: H160) -> bool {
+        self.backend.exists(address)
+    }
+
+    fn basic(&self, address: H160) -> Basic {
+        let basic = self.backend.basic(address);
+        self.report(BackendEvent::Basic {
+            address,
+            basic: &basic,
+        });
+        basic
+    }
+
+    fn code(&self, address: H160) -> Vec<u8> {
+        let code = self.backend.code(address);
+        self.report(BackendEvent::Code {
+            address,
+            code: &code,
+        });
+        code
+    }
+
+    fn storage(&self, address: H160, index: H256) -> H256 {
+        let value = self.backend.storage(address, index);
+        self.report(BackendEvent::Storage {
+            address,
+            index,
+            value,
+        });
+        value
+    }
+
+    fn original_storage(&self, address: H160, index: H256) -> Option<H256> {
+        let value = self.backend.original_storage(address, index);
+        if let Some(value) = value {
+            self.report(BackendEvent::Storage {
+                address,
+                index,
+                value,
+            });
+        }
+        value
+    }
+}
@@ -0,0 +1,221 @@
+/// Prestate tracer, reporting all state read by an execution and how the execution changed it,
+/// like geth's `prestateTracer` in diff mode.
+use std::collections::BTreeMap;
+
+use evm::backend::Apply;
+use jsonrpc_core::{serde_json, Value};
+use primitive_types::{H160, H256, U256};
+
+use super::{BackendEvent, TraceOutcome, Tracer};
+
+/// The part of an account that was read or written. Balances are in Eth units.
+#[derive(Clone, Default, PartialEq, serde::Serialize)]
+struct AccountState {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    balance: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<U256>,
+    /// In hex.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    storage: BTreeMap<H256, H256>,
+}
+
+#[derive(serde::Serialize)]
+struct PrestateResult<'a> {
+    pre: &'a BTreeMap<H160, AccountState>,
+    post: BTreeMap<H160, AccountState>,
+}
+
+#[derive(Default)]
+pub struct PrestateTracer {
+    // The state of the backend, as first read.
+    pre: BTreeMap<H160, AccountState>,
+}
+
+impl Tracer for PrestateTracer {
+    fn backend_event(&mut self, event: BackendEvent) {
+        match event {
+            BackendEvent::Basic { address, basic } => {
+                let account = self.pre.entry(address).or_default();
+                account.balance.get_or_insert(basic.balance);
+                account.nonce.get_or_insert(basic.nonce);
+            }
+            BackendEvent::Code { address, code } => {
+                let account = self.pre.entry(address).or_default();
+                account.code.get_or_insert_with(|| hex::encode(code));
+            }
+            BackendEvent::Storage {
+                address,
+                index,
+                value,
+            } => {
+                let account = self.pre.entry(address).or_default();
+                account.storage.entry(index).or_insert(value);
+            }
+        }
+    }
+
+    // Accounts deleted by the execution are in `pre` only. Changed accounts are in
+    // `post` with the fields that differ from `pre`.
+    fn result(&self, outcome: &TraceOutcome) -> Value {
+        let post = outcome
+            .apply
+            .iter()
+            .filter_map(|apply| match apply {
+                Apply::Modify {
+                    address,
+                    basic,
+                    code,
+                    storage,
+                    ..
+                } => {
+                    let pre = self.pre.get(address).cloned().unwrap_or_default();
+                    let changed = |old: Option<U256>, new: U256| (old != Some(new)).then_some(new);
+                    let code = code.as_ref().map(hex::encode);
+                    let account = AccountState {
+                        balance: changed(pre.balance, basic.balance),
+                        nonce: changed(pre.nonce, basic.nonce),
+                        code: code.filter(|code| pre.code.as_ref() != Some(code)),
+                        storage: storage
+                            .iter()
+                            .filter(|(index, value)| pre.storage.get(index) != Some(value))
+                            .map(|(index, value)| (*index, *value))
+                            .collect(),
+                    };
+                    (account != AccountState::default()).then_some((*address, account))
+                }
+                Apply::Delete { .. } => None,
+            })
+            .collect();
+        serde_json::to_value(PrestateResult {
+            pre: &self.pre,
+            post,
+        })
+        .unwrap_or(Value::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::forks::{Fork, ForkSchedule};
+    use crate::gas::{GasRounding, GasScaling};
+    use crate::memorybackend::{InMemoryBackend, MemoryAccount, MemoryState};
+    use crate::overrides::AccountOverride;
+    use crate::scillabackend::PrecisionPolicy;
+    use crate::tracers::TracerConfig;
+    use crate::{run_evm_impl, RunParams};
+
+    fn json(value: impl serde::Serialize) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    // The key of `address` in `pre` and `post`.
+    fn key(address: H160) -> String {
+        json(address).as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn pre_and_post_of_a_transaction() {
+        let (caller, address, doomed, beneficiary) = (
+            H160::from_str("0x000000000000000000000000000073656e646572").unwrap(),
+            H160::from_str("0x0000000000000000000000007265636569766572").unwrap(),
+            H160::repeat_byte(0xdd),
+            H160::repeat_byte(0xee),
+        );
+        let slot = H256::zero();
+        let mut state = MemoryState::default();
+        // PUSH1 42 PUSH1 0 SSTORE, CALL(GAS, doomed, 0, 0, 0, 0, 0) STOP
+        let code = format!(
+            "602a6000556000600060006000600073{}5af100",
+            hex::encode(doomed)
+        );
+        state.accounts.insert(
+            address,
+            MemoryAccount {
+                code: hex::decode(code).unwrap(),
+                storage: [(slot, H256::from_low_u64_be(1))].into_iter().collect(),
+                ..Default::default()
+            },
+        );
+        // PUSH20 beneficiary SELFDESTRUCT
+        state.accounts.insert(
+            doomed,
+            MemoryAccount {
+                balance: U256::from(5),
+                code: hex::decode(format!("73{}ff", hex::encode(beneficiary))).unwrap(),
+                ..Default::default()
+            },
+        );
+        let backend = InMemoryBackend::new(state, 1, PrecisionPolicy::Dust);
+        let overrides = [
+            (
+                caller,
+                AccountOverride {
+                    balance: Some(U256::from(100)),
+                    ..Default::default()
+                },
+            ),
+            (
+                address,
+                AccountOverride {
+                    state_diff: Some([(slot, H256::from_low_u64_be(7))].into_iter().collect()),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let params = RunParams {
+            address: key(address),
+            caller: key(caller),
+            apparent_value: "3".to_string(),
+            gas_limit: 100_000,
+            mode: Some("transaction".to_string()),
+            overrides: Some(overrides.into_iter().collect()),
+            tracer: Some(TracerConfig::PrestateTracer),
+            ..Default::default()
+        };
+        let (result, _) = run_evm_impl(
+            params,
+            backend,
+            false,
+            GasScaling::new(1, GasRounding::Floor),
+            ForkSchedule::new(Fork::London),
+        )
+        .await
+        .unwrap();
+        assert!(result.exit_reason.is_succeed());
+        let trace = result.trace.unwrap();
+        let (pre, post) = (&trace["pre"], &trace["post"]);
+
+        // As read, after the overrides.
+        assert_eq!(pre[key(caller)]["balance"], json(U256::from(100)));
+        assert_eq!(pre[key(caller)]["nonce"], json(U256::zero()));
+        assert_eq!(
+            pre[key(address)]["storage"],
+            json(BTreeMap::from([(slot, H256::from_low_u64_be(7))]))
+        );
+        assert_eq!(pre[key(doomed)]["balance"], json(U256::from(5)));
+
+        // Only what changed.
+        assert_eq!(
+            post[key(caller)],
+            json(BTreeMap::from([
+                ("balance", U256::from(97)),
+                ("nonce", U256::one()),
+            ]))
+        );
+        let callee = post[key(address)].as_object().unwrap();
+        assert_eq!(callee.len(), 2);
+        assert_eq!(callee["balance"], json(U256::from(3)));
+        assert_eq!(
+            callee["storage"],
+            json(BTreeMap::from([(slot, H256::from_low_u64_be(42))]))
+        );
+        assert_eq!(post[key(beneficiary)]["balance"], json(U256::from(5)));
+        assert!(post.get(key(doomed)).is_none());
+    }
+}
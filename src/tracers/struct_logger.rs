@@ -0,0 +1,388 @@
+/// Opcode tracer producing geth's `structLogs`, as consumed by Remix and Hardhat-style debuggers.
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use evm::Capture;
+use evm_gasometer::tracing::{Event as GasometerEvent, Snapshot};
+use evm_runtime::tracing::Event as RuntimeEvent;
+use jsonrpc_core::{serde_json, Value};
+use primitive_types::{H160, H256, U256};
+
+use super::{TraceOutcome, Tracer};
+
+/// Options of geth's struct logger.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct StructLoggerConfig {
+    pub disable_stack: bool,
+    pub disable_memory: bool,
+    pub disable_storage: bool,
+}
+
+/// One executed opcode.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StructLog {
+    pc: usize,
+    op: Cow<'static, str>,
+    /// Gas left before the opcode.
+    gas: u64,
+    gas_cost: u64,
+    depth: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stack: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory: Option<Vec<String>>,
+    /// Slots of the current contract accessed so far, only for SLOAD and SSTORE.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    storage: Option<BTreeMap<String, String>>,
+    // Whether `gas` was taken from the gasometer already.
+    #[serde(skip)]
+    gas_recorded: bool,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StructLoggerResult<'a> {
+    gas: u64,
+    failed: bool,
+    return_value: String,
+    struct_logs: &'a [StructLog],
+}
+
+pub struct StructLogger {
+    config: StructLoggerConfig,
+    logs: Vec<StructLog>,
+    // Number of calls and creates entered, including the top-level one.
+    depth: usize,
+    storage: BTreeMap<H160, BTreeMap<H256, H256>>,
+}
+
+impl StructLogger {
+    pub fn new(config: StructLoggerConfig) -> Self {
+        Self {
+            config,
+            logs: Vec::new(),
+            depth: 0,
+            storage: BTreeMap::new(),
+        }
+    }
+
+    // The gasometer records the costs of an opcode after the runtime announced it.
+    fn record_gas(&mut self, cost: u64, snapshot: Option<Snapshot>) {
+        if let Some(log) = self.logs.last_mut() {
+            if !log.gas_recorded {
+                if let Some(snapshot) = snapshot {
+                    log.gas = snapshot
+                        .gas_limit
+                        .saturating_sub(snapshot.used_gas)
+                        .saturating_sub(snapshot.memory_gas);
+                }
+                log.gas_recorded = true;
+            }
+            log.gas_cost += cost;
+        }
+    }
+
+    fn record_storage(&mut self, address: H160, index: H256, value: H256) {
+        let storage = self.storage.entry(address).or_default();
+        storage.insert(index, value);
+        if self.config.disable_storage {
+            return;
+        }
+        let storage = storage
+            .iter()
+            .map(|(index, value)| (hex::encode(index), hex::encode(value)))
+            .collect();
+        if let Some(log) = self.logs.last_mut() {
+            log.storage = Some(storage);
+        }
+    }
+}
+
+impl Tracer for StructLogger {
+    fn evm_event(&mut self, event: evm::tracing::Event) {
+        match event {
+            evm::tracing::Event::Call { .. } | evm::tracing::Event::Create { .. } => {
+                self.depth += 1
+            }
+            evm::tracing::Event::Exit { .. } => self.depth = self.depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    fn runtime_event(&mut self, event: RuntimeEvent) {
+        match event {
+            RuntimeEvent::Step {
+                opcode,
+                position,
+                stack,
+                memory,
+                ..
+            } => {
+                let stack = (!self.config.disable_stack).then(|| {
+                    stack
+                        .data()
+                        .iter()
+                        .map(|value| format!("{:#x}", U256::from_big_endian(value.as_bytes())))
+                        .collect()
+                });
+                let memory = (!self.config.disable_memory)
+                    .then(|| memory.data().chunks(32).map(hex::encode).collect());
+                self.logs.push(StructLog {
+                    pc: position.as_ref().map_or(0, |pc| *pc),
+                    op: opcode_name(opcode.0),
+                    gas: 0,
+                    gas_cost: 0,
+                    depth: self.depth,
+                    error: None,
+                    stack,
+                    memory,
+                    storage: None,
+                    gas_recorded: false,
+                });
+            }
+            RuntimeEvent::StepResult {
+                result: Err(Capture::Exit(reason)),
+                ..
+            } if !reason.is_succeed() => {
+                if let Some(log) = self.logs.last_mut() {
+                    log.error = Some(format!("{:?}", reason));
+                }
+            }
+            RuntimeEvent::SLoad {
+                address,
+                index,
+                value,
+            }
+            | RuntimeEvent::SStore {
+                address,
+                index,
+                value,
+            } => self.record_storage(address, index, value),
+            _ => {}
+        }
+    }
+
+    fn gasometer_event(&mut self, event: GasometerEvent) {
+        match event {
+            GasometerEvent::RecordCost { cost, snapshot } => self.record_gas(cost, snapshot),
+            GasometerEvent::RecordDynamicCost {
+                gas_cost,
+                memory_gas,
+                snapshot,
+                ..
+            } => {
+                // `memory_gas` is the total for the memory after the expansion.
+                let memory_cost =
+                    snapshot.map_or(0, |snapshot| memory_gas.saturating_sub(snapshot.memory_gas));
+                self.record_gas(gas_cost + memory_cost, snapshot)
+            }
+            _ => {}
+        }
+    }
+
+    fn result(&self, outcome: &TraceOutcome) -> Value {
+        serde_json::to_value(StructLoggerResult {
+            gas: outcome.used_gas,
+            failed: !outcome.exit_reason.is_succeed(),
+            return_value: hex::encode(outcome.return_value),
+            struct_logs: &self.logs,
+        })
+        .unwrap_or(Value::Null)
+    }
+}
+
+fn opcode_name(opcode: u8) -> Cow<'static, str> {
+    let name = match opcode {
+        0x00 => "STOP",
+        0x01 => "ADD",
+        0x02 => "MUL",
+        0x03 => "SUB",
+        0x04 => "DIV",
+        0x05 => "SDIV",
+        0x06 => "MOD",
+        0x07 => "SMOD",
+        0x08 => "ADDMOD",
+        0x09 => "MULMOD",
+        0x0a => "EXP",
+        0x0b => "SIGNEXTEND",
+        0x10 => "LT",
+        0x11 => "GT",
+        0x12 => "SLT",
+        0x13 => "SGT",
+        0x14 => "EQ",
+        0x15 => "ISZERO",
+        0x16 => "AND",
+        0x17 => "OR",
+        0x18 => "XOR",
+        0x19 => "NOT",
+        0x1a => "BYTE",
+        0x1b => "SHL",
+        0x1c => "SHR",
+        0x1d => "SAR",
+        0x20 => "SHA3",
+        0x30 => "ADDRESS",
+        0x31 => "BALANCE",
+        0x32 => "ORIGIN",
+        0x33 => "CALLER",
+        0x34 => "CALLVALUE",
+        0x35 => "CALLDATALOAD",
+        0x36 => "CALLDATASIZE",
+        0x37 => "CALLDATACOPY",
+        0x38 => "CODESIZE",
+        0x39 => "CODECOPY",
+        0x3a => "GASPRICE",
+        0x3b => "EXTCODESIZE",
+        0x3c => "EXTCODECOPY",
+        0x3d => "RETURNDATASIZE",
+        0x3e => "RETURNDATACOPY",
+        0x3f => "EXTCODEHASH",
+        0x40 => "BLOCKHASH",
+        0x41 => "COINBASE",
+        0x42 => "TIMESTAMP",
+        0x43 => "NUMBER",
+        0x44 => "DIFFICULTY",
+        0x45 => "GASLIMIT",
+        0x46 => "CHAINID",
+        0x47 => "SELFBALANCE",
+        0x48 => "BASEFEE",
+        0x50 => "POP",
+        0x51 => "MLOAD",
+        0x52 => "MSTORE",
+        0x53 => "MSTORE8",
+        0x54 => "SLOAD",
+        0x55 => "SSTORE",
+        0x56 => "JUMP",
+        0x57 => "JUMPI",
+        0x58 => "PC",
+        0x59 => "MSIZE",
+        0x5a => "GAS",
+        0x5b => "JUMPDEST",
+        0x60..=0x7f => return Cow::Owned(format!("PUSH{}", opcode - 0x5f)),
+        0x80..=0x8f => return Cow::Owned(format!("DUP{}", opcode - 0x7f)),
+        0x90..=0x9f => return Cow::Owned(format!("SWAP{}", opcode - 0x8f)),
+        0xa0..=0xa4 => return Cow::Owned(format!("LOG{}", opcode - 0xa0)),
+        0xf0 => "CREATE",
+        0xf1 => "CALL",
+        0xf2 => "CALLCODE",
+        0xf3 => "RETURN",
+        0xf4 => "DELEGATECALL",
+        0xf5 => "CREATE2",
+        0xfa => "STATICCALL",
+        0xfd => "REVERT",
+        0xfe => "INVALID",
+        0xff => "SELFDESTRUCT",
+        _ => return Cow::Owned(format!("opcode {:#x} not defined", opcode)),
+    };
+    Cow::Borrowed(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forks::{Fork, ForkSchedule};
+    use crate::gas::{GasRounding, GasScaling};
+    use crate::memorybackend::{InMemoryBackend, MemoryState};
+    use crate::scillabackend::PrecisionPolicy;
+    use crate::tracers::TracerConfig;
+    use crate::{run_evm_impl, RunParams};
+
+    async fn trace_code(code: &str, config: StructLoggerConfig) -> Value {
+        let params = RunParams {
+            address: "0x0000000000000000000000007265636569766572".to_string(),
+            caller: "0x000000000000000000000000000073656e646572".to_string(),
+            code: code.to_string(),
+            apparent_value: "0".to_string(),
+            gas_limit: 100_000,
+            tracer: Some(TracerConfig::StructLogger(config)),
+            ..Default::default()
+        };
+        let backend = InMemoryBackend::new(MemoryState::default(), 1, PrecisionPolicy::Dust);
+        let (result, _) = run_evm_impl(
+            params,
+            backend,
+            false,
+            GasScaling::new(1, GasRounding::Floor),
+            ForkSchedule::new(Fork::London),
+        )
+        .await
+        .unwrap();
+        assert!(result.exit_reason.is_succeed());
+        result.trace.unwrap()
+    }
+
+    #[test]
+    fn opcode_names() {
+        assert_eq!(opcode_name(0x00), "STOP");
+        assert_eq!(opcode_name(0x60), "PUSH1");
+        assert_eq!(opcode_name(0x7f), "PUSH32");
+        assert_eq!(opcode_name(0x8f), "DUP16");
+        assert_eq!(opcode_name(0x90), "SWAP1");
+        assert_eq!(opcode_name(0xa4), "LOG4");
+        assert_eq!(opcode_name(0x0c), "opcode 0xc not defined");
+    }
+
+    #[tokio::test]
+    async fn store_and_load() {
+        // PUSH1 1 PUSH1 0 SSTORE PUSH1 0 SLOAD STOP
+        let code = "600160005560005400";
+        let trace = trace_code(code, StructLoggerConfig::default()).await;
+        assert_eq!(trace["gas"], 22_209);
+        assert_eq!(trace["failed"], false);
+        assert_eq!(trace["returnValue"], "");
+
+        let stored = serde_json::to_value(BTreeMap::from([(
+            format!("{:064x}", 0),
+            format!("{:064x}", 1),
+        )]))
+        .unwrap();
+        // pc, op, gas, gasCost, stack, storage
+        let expected = [
+            (0, "PUSH1", 100_000, 3, vec![], None),
+            (2, "PUSH1", 99_997, 3, vec!["0x1"], None),
+            // A cold slot set from zero.
+            (
+                4,
+                "SSTORE",
+                99_994,
+                22_100,
+                vec!["0x1", "0x0"],
+                Some(&stored),
+            ),
+            (5, "PUSH1", 77_894, 3, vec![], None),
+            // The slot is warm now.
+            (7, "SLOAD", 77_891, 100, vec!["0x0"], Some(&stored)),
+            (8, "STOP", 77_791, 0, vec!["0x1"], None),
+        ];
+        let logs = trace["structLogs"].as_array().unwrap();
+        assert_eq!(logs.len(), expected.len());
+        for (log, (pc, op, gas, gas_cost, stack, storage)) in logs.iter().zip(expected) {
+            assert_eq!(log["pc"], pc, "{}", log);
+            assert_eq!(log["op"], op, "{}", log);
+            assert_eq!(log["gas"], gas, "{}", log);
+            assert_eq!(log["gasCost"], gas_cost, "{}", log);
+            assert_eq!(log["depth"], 1, "{}", log);
+            assert_eq!(log["stack"], serde_json::json!(stack), "{}", log);
+            assert_eq!(log["memory"], serde_json::json!([]), "{}", log);
+            assert_eq!(log.get("storage"), storage, "{}", log);
+            assert!(log.get("error").is_none(), "{}", log);
+        }
+
+        let disabled = StructLoggerConfig {
+            disable_stack: true,
+            disable_memory: true,
+            disable_storage: true,
+        };
+        let trace = trace_code(code, disabled).await;
+        let logs = trace["structLogs"].as_array().unwrap();
+        assert_eq!(logs.len(), expected.len());
+        for log in logs {
+            assert!(log.get("stack").is_none(), "{}", log);
+            assert!(log.get("memory").is_none(), "{}", log);
+            assert!(log.get("storage").is_none(), "{}", log);
+        }
+    }
+}
@@ -0,0 +1,15 @@
+# This test traces the state accessed by compiled 'storage.sol', method 'store' (6057361d) with some argument: (0x3039)
+
+curl -d '{
+    "id": "1",
+    "jsonrpc": "2.0",
+    "method": "trace_state",
+    "params": [
+      "0x00112233445566778899AABBCCDDEEFF00112233",
+      "0x00112233445566778899AABBCCDDEEFF00112233",
+      "608060405234801561001057600080fd5b50600436106100415760003560e01c80632e64cec11461004657806336b62288146100645780636057361d1461006e575b600080fd5b61004e61008a565b60405161005b91906100d0565b60405180910390f35b61006c610093565b005b6100886004803603810190610083919061011c565b6100ad565b005b60008054905090565b600073ffffffffffffffffffffffffffffffffffffffff16ff5b8060008190555050565b6000819050919050565b6100ca816100b7565b82525050565b60006020820190506100e560008301846100c1565b92915050565b600080fd5b6100f9816100b7565b811461010457600080fd5b50565b600081359050610116816100f0565b92915050565b600060208284031215610132576101316100eb565b5b600061014084828501610107565b9150509291505056fea2646970667358221220c11cc7b07b2f889ced02511e03fe7604a33d010cde91fe1d68869188cf2e3be964736f6c634300080d0033",
+      "6057361d0000000000000000000000000000000000000000000000000000000000003039",
+      "00",
+      100000
+    ]
+}' -H "Content-Type: application/json" -X POST "http://localhost:3333"
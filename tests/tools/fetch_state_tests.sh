@@ -0,0 +1,35 @@
+#!/bin/sh
+# Fetches the GeneralStateTests fixtures of ethereum/tests, for the conformance tests in
+# src/statetests.rs.
+#
+#   fetch_state_tests.sh            fetches the pinned subset into tests/GeneralStateTests,
+#                                   to be committed with the VERSION it writes.
+#   fetch_state_tests.sh --all DIR  fetches all of them into DIR, to run with STATE_TESTS=DIR.
+#
+# Set TAG to update the fixtures to another release of ethereum/tests.
+set -e
+
+TAG=${TAG:-v10.4}
+SUBSET="stExample stArgsZeroOneBalance stSStoreTest"
+
+if [ "$1" = "--all" ]; then
+    DEST=${2:?usage: $0 --all DIR}
+    PATHS=GeneralStateTests
+else
+    DEST=$(dirname "$0")/../GeneralStateTests
+    PATHS=
+    for dir in $SUBSET; do
+        PATHS="$PATHS GeneralStateTests/$dir"
+    done
+fi
+
+TMP=$(mktemp -d)
+trap 'rm -rf "$TMP"' EXIT
+
+git clone --quiet --depth 1 --branch "$TAG" --filter=blob:none --sparse \
+    https://github.com/ethereum/tests.git "$TMP"
+# shellcheck disable=SC2086
+git -C "$TMP" sparse-checkout set $PATHS
+rm -rf "$DEST"
+cp -r "$TMP/GeneralStateTests" "$DEST"
+echo "$TAG" > "$DEST/VERSION"